@@ -0,0 +1,133 @@
+use anyhow::{Context as _, Result};
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SUFFIX: &str = ".yurt-backup-";
+
+/// Path of the backup of `path` taken at `timestamp`
+fn backup_path(path: &Path, timestamp: u64) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!("{SUFFIX}{timestamp}"));
+    path.with_file_name(name)
+}
+
+/// Move the file or directory at `path` to `<path>.yurt-backup-<timestamp>`
+pub fn create<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
+    let path = path.as_ref();
+    let mut timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let mut backup = backup_path(path, timestamp);
+    while backup.symlink_metadata().is_ok() {
+        timestamp += 1;
+        backup = backup_path(path, timestamp);
+    }
+    fs::rename(path, &backup).with_context(|| {
+        format!(
+            "Failed to back up {} to {}",
+            path.display(),
+            backup.display()
+        )
+    })?;
+    Ok(backup)
+}
+
+/// Most recent backup of `path` (if any)
+pub fn latest<P: AsRef<Path>>(path: P) -> Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(None);
+    };
+    let prefix = format!("{}{SUFFIX}", name.to_string_lossy());
+    let dir = match dir.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => dir,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read directory: {}", dir.display()))
+        }
+    };
+    let mut latest = None;
+    for entry in entries {
+        let entry = entry?;
+        let timestamp = entry
+            .file_name()
+            .to_str()
+            .and_then(|file| file.strip_prefix(&prefix))
+            .and_then(|timestamp| timestamp.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp {
+            if latest
+                .as_ref()
+                .is_none_or(|(newest, _)| timestamp > *newest)
+            {
+                latest = Some((timestamp, backup_path(path, timestamp)));
+            }
+        }
+    }
+    Ok(latest.map(|(_, backup)| backup))
+}
+
+/// Move the most recent backup of `path` back into place
+pub fn restore<P: AsRef<Path>>(path: P) -> Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    match latest(path)? {
+        Some(backup) => {
+            log::info!("Restoring {} to {}", backup.display(), path.display());
+            fs::rename(&backup, path).with_context(|| {
+                format!(
+                    "Failed to restore {} to {}",
+                    backup.display(),
+                    path.display()
+                )
+            })?;
+            Ok(Some(backup))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_and_restore() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let path = dir.path().join("config");
+        fs::write(&path, "first").unwrap();
+        let first = create(&path).expect("Failed to back up file");
+        fs::write(&path, "second").unwrap();
+        let second = create(&path).expect("Failed to back up file");
+        assert_ne!(first, second);
+        assert!(!path.exists());
+        assert_eq!(latest(&path).unwrap(), Some(second.clone()));
+        assert_eq!(restore(&path).unwrap(), Some(second));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(latest(&path).unwrap(), Some(first));
+    }
+
+    #[test]
+    fn create_directory() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let path = dir.path().join("config");
+        fs::create_dir_all(path.join("nested")).unwrap();
+        let backup = create(&path).expect("Failed to back up directory");
+        assert!(backup.join("nested").is_dir());
+        restore(&path).expect("Failed to restore directory");
+        assert!(path.join("nested").is_dir());
+    }
+
+    #[test]
+    fn latest_missing() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        assert_eq!(latest(dir.path().join("config")).unwrap(), None);
+        assert_eq!(restore(dir.path().join("config")).unwrap(), None);
+    }
+}
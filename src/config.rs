@@ -1,16 +1,20 @@
+#![allow(clippy::non_std_lazy_statics)]
+
 use crate::{
     context::Context,
-    specs::{BuildSpec, BuildUnit, ResolveInto},
+    source::Source,
+    specs::{BuildSpec, BuildUnit, ResolveInto, ShellCommand},
     yaml_example_doc,
 };
 
-use anyhow::{bail, Context as _, Result};
+use anyhow::{bail, Context as _, Error, Result};
 use clap::crate_version;
+use indexmap::IndexMap;
 use lazy_static::lazy_static;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
 use std::{
-    env,
+    env, fmt,
     fs::File,
     io::BufReader,
     path::{Path, PathBuf},
@@ -46,23 +50,81 @@ impl ResolvedConfig {
     }
 
     #[inline]
-    pub fn for_each_unit<F>(&self, f: F) -> Result<()>
+    pub fn for_each_unit<F>(&self, mut f: F) -> Result<()>
     where
-        F: Fn(&BuildUnit, &Context) -> Result<()>,
+        F: FnMut(&BuildUnit, &Context) -> Result<()>,
     {
         self.build
             .iter()
             .try_for_each(|unit| f(unit, &self.context))
     }
 
+    /// Check if the build has a unit that manages the same thing as `unit`
+    pub fn contains(&self, unit: &BuildUnit) -> bool {
+        self.build.iter().any(|other| other.is_same(unit))
+    }
+
     pub fn into_config(self) -> Config {
         Config {
             version: self.version,
+            relative_links: None,
+            facts: None,
             build: self.build.into_iter().map(Into::into).collect(),
+            source: None,
         }
     }
 }
 
+/// Outcome of running an action on each unit of a build
+#[derive(Debug, Default)]
+pub struct Summary {
+    succeeded: usize,
+    skipped: usize,
+    failed: Vec<(String, Error)>,
+}
+
+impl Summary {
+    /// Count a unit that the action does not apply to
+    pub fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Count the `result` of running the action on `unit`
+    pub fn record(&mut self, unit: &BuildUnit, result: Result<()>) {
+        match result {
+            Ok(()) => self.succeeded += 1,
+            Err(error) => {
+                log::error!("{unit}: {error:#}");
+                self.failed.push((unit.to_string(), error));
+            }
+        }
+    }
+
+    /// Fail if the action failed for any unit
+    pub fn into_result(self) -> Result<()> {
+        match self.failed.len() {
+            0 => Ok(()),
+            count => bail!("{count} of {} units failed", self.succeeded + count),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} failed, {} skipped",
+            self.succeeded,
+            self.failed.len(),
+            self.skipped
+        )?;
+        for (unit, error) in &self.failed {
+            write!(f, "\n  {unit}: {error:#}")?;
+        }
+        Ok(())
+    }
+}
+
 /// Top level yurt build file YAML object.
 ///
 /// Order of build steps is preserved after resolution.
@@ -75,17 +137,31 @@ impl ResolvedConfig {
 pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<VersionReq>,
+    /// Default for [`!link`][BuildSpec::Link] `relative` (applies to this file and its includes)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative_links: Option<bool>,
+    /// Shell commands whose output is available as `${{ fact.<name> }}` (evaluated on first use)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facts: Option<IndexMap<String, ShellCommand>>,
     build: Vec<BuildSpec>,
+    /// Location of the build file (used to resolve relative includes)
+    #[serde(skip)]
+    source: Option<Source>,
 }
 
 impl Config {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
         File::open(path)
             .map(BufReader::new)
             .context("Failed to open build file")
             .and_then(|reader| {
                 serde_yaml::from_reader(reader).context("Failed to deserialize build file")
             })
+            .map(|config| Self {
+                source: Some(Source::Path(path.to_path_buf())),
+                ..config
+            })
     }
 
     pub fn from_env() -> Result<Self> {
@@ -95,27 +171,40 @@ impl Config {
             .and_then(Self::from_path)
     }
 
+    /// Load a remote build file (`https://...` or `git+https://...#path@ref`)
     pub fn from_url(url: &str) -> Result<Self> {
-        minreq::get(url)
-            .send()
-            .context("Failed to reach remote build file")
-            .and_then(|response| {
-                serde_yaml::from_reader(response.as_bytes())
-                    .context("Failed to deserialize remote build file")
-            })
+        Source::parse(url).and_then(Self::from_source)
+    }
+
+    pub fn from_source(source: Source) -> Result<Self> {
+        match source {
+            Source::Path(path) => Self::from_path(path),
+            remote => remote
+                .read()
+                .and_then(|yaml| {
+                    serde_yaml::from_str(&yaml).context("Failed to deserialize remote build file")
+                })
+                .map(|config| Self {
+                    source: Some(remote),
+                    ..config
+                }),
+        }
+    }
+
+    fn check_version(&self) -> Result<()> {
+        match self.version {
+            Some(ref req) if !req.matches(&VERSION) => {
+                bail!("Version requirement not satisfied: {} ({})", req, *VERSION)
+            }
+            _ => Ok(()),
+        }
     }
 
     pub fn resolve(self, mut context: Context) -> Result<ResolvedConfig> {
-        // Check version
-        let version = match self.version {
-            Some(req) if req.matches(&VERSION) => Some(req),
-            Some(req) => bail!("Version requirement not satisfied: {} ({})", req, *VERSION),
-            None => None,
-        };
-        // Resolve build
+        self.check_version()?;
+        let version = self.version.clone();
         Ok(ResolvedConfig {
             build: self
-                .build
                 .resolve_into_new(&mut context)
                 .context("Failed to resolve build")?,
             version,
@@ -123,15 +212,67 @@ impl Config {
         })
     }
 
+    /// Build file containing only `build`
+    pub fn from_build(build: Vec<BuildSpec>) -> Self {
+        Self {
+            version: None,
+            relative_links: None,
+            facts: None,
+            build,
+            source: None,
+        }
+    }
+
     pub fn yaml(&self) -> Result<String> {
         serde_yaml::to_string(&self).context("Failed to serialize config")
     }
 }
 
+impl ResolveInto for Config {
+    fn resolve_into(self, context: &mut Context, output: &mut Vec<BuildUnit>) -> Result<()> {
+        self.check_version()?;
+        let relative_links = context.relative_links;
+        if let Some(relative) = self.relative_links {
+            context.relative_links = relative;
+        }
+        for (name, command) in self.facts.into_iter().flatten() {
+            context.add_fact(name, command);
+        }
+        let result = match self.source {
+            Some(source) => {
+                context.push_file(source)?;
+                let result = self.build.resolve_into(context, output);
+                context.pop_file();
+                result
+            }
+            None => self.build.resolve_into(context, output),
+        };
+        context.relative_links = relative_links;
+        result
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
 
+    #[test]
+    fn summary() {
+        let unit =
+            BuildUnit::try_from(serde_yaml::from_str::<BuildSpec>("!package { name: p }").unwrap())
+                .unwrap();
+        let mut summary = Summary::default();
+        summary.record(&unit, Ok(()));
+        summary.skip();
+        assert_eq!(summary.to_string(), "1 succeeded, 0 failed, 1 skipped");
+        summary.record(&unit, Err(anyhow::anyhow!("broken")));
+        assert_eq!(
+            summary.to_string(),
+            "1 succeeded, 1 failed, 1 skipped\n  package p: broken"
+        );
+        assert!(summary.into_result().is_err());
+    }
+
     mod yaml {
         use super::*;
         use crate::YurtArgs;
@@ -210,6 +351,12 @@ pub mod tests {
             test_case!(exclude);
             test_case!(include);
             test_case!(shell);
+            test_case!(include_file);
+            test_case!(link_mode);
+            test_case!(relative_links);
+            test_case!(locale_vars);
+            test_case!(facts);
+            test_case!(comparisons);
         }
 
         mod invalid_parse {
@@ -244,6 +391,8 @@ pub mod tests {
             }
 
             test_case!(version_mismatch);
+            test_case!(include_cycle);
+            test_case!(include_version_mismatch);
         }
     }
 }
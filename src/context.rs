@@ -1,40 +1,162 @@
-use crate::specs::PackageManager;
-
-use anyhow::Result;
+use crate::{
+    matcher::Matcher,
+    source::Source,
+    specs::{PackageManager, ShellCommand},
+    state::Stamps,
+};
+use parse::{Key, ObjectKey};
+
+use anyhow::{bail, Context as _, Result};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::{cell::OnceCell, env, fs, path::Path, rc::Rc};
 
 #[derive(Debug, Clone)]
 pub struct Context {
     pub locale: Locale,
     pub managers: IndexMap<String, PackageManager>,
     pub variables: parse::KeyStack,
+    /// Create relative symlinks unless a link specifies otherwise
+    pub relative_links: bool,
+    /// Records written by the resolved units
+    pub stamps: Stamps,
     home_dir: String,
+    /// Stack of build files being resolved (innermost last)
+    files: Vec<Source>,
+    /// User-defined facts (`${{ fact.<name> }}`)
+    facts: IndexMap<String, Fact>,
+}
+
+/// Shell command whose output is evaluated on first use
+#[derive(Debug, Clone)]
+struct Fact {
+    command: ShellCommand,
+    /// Output shared by every copy of the fact
+    value: Rc<OnceCell<String>>,
+}
+
+impl ObjectKey for Fact {
+    const OBJECT_NAME: &'static str = "fact";
+}
+
+impl Fact {
+    /// Output of the fact `name` (the command runs at most once)
+    fn value(&self, name: &str) -> Result<String> {
+        if let Some(value) = self.value.get() {
+            return Ok(value.clone());
+        }
+        let value = self
+            .command
+            .exec_output()
+            .with_context(|| format!("Failed to evaluate fact: {name}"))?;
+        log::debug!("Fact {name} = {value:?}");
+        Ok(self.value.get_or_init(|| value).clone())
+    }
+}
+
+/// Value of `key` (facts are evaluated on demand)
+fn lookup(variables: &parse::KeyStack, facts: &IndexMap<String, Fact>, key: Key) -> Result<String> {
+    match key {
+        Key::ObjectAttr { object, attr } if object == Fact::OBJECT_NAME => facts
+            .get(&attr)
+            .with_context(|| format!("Fact is not defined: {attr}"))?
+            .value(&attr),
+        key => variables.try_get(&key),
+    }
+}
+
+/// Variables and facts captured from a [`Context`] to resolve keys after resolution
+#[derive(Debug, Clone)]
+pub struct Variables {
+    variables: parse::KeyStack,
+    facts: IndexMap<String, Fact>,
+}
+
+impl Variables {
+    pub fn parse_str(&self, input: &str) -> Result<String> {
+        parse::replace(input, |key| lookup(&self.variables, &self.facts, key))
+    }
 }
 
 impl Context {
     pub fn new(locale: Locale) -> Self {
+        let mut variables = parse::KeyStack::new();
+        for (attr, value) in locale.attrs() {
+            variables.push(Locale::object_key(attr), value.to_string());
+        }
         Self {
             locale,
             managers: IndexMap::new(),
-            variables: parse::KeyStack::new(),
+            variables,
+            relative_links: false,
+            stamps: Stamps::default(),
             home_dir: dirs::home_dir()
                 .as_deref()
                 .and_then(Path::to_str)
                 .unwrap_or("~")
                 .to_string(),
+            files: Vec::new(),
+            facts: IndexMap::new(),
+        }
+    }
+
+    /// Define the fact `name` as the output of `command` (replaces any previous definition)
+    pub fn add_fact(&mut self, name: String, command: ShellCommand) {
+        let fact = Fact {
+            command,
+            value: Rc::new(OnceCell::new()),
+        };
+        self.facts.insert(name, fact);
+    }
+
+    /// Output of the fact `name` (the command runs at most once)
+    pub fn fact(&self, name: &str) -> Result<String> {
+        lookup(&self.variables, &self.facts, Fact::object_key(name))
+    }
+
+    /// Variables and facts in scope (facts stay shared with this context)
+    pub fn capture(&self) -> Variables {
+        Variables {
+            variables: self.variables.clone(),
+            facts: self.facts.clone(),
+        }
+    }
+
+    /// Enter a build file; fails if the file is already being resolved
+    pub fn push_file(&mut self, source: Source) -> Result<()> {
+        let source = source.canonicalize();
+        if self.files.contains(&source) {
+            bail!("Build file includes itself: {source}");
+        }
+        self.files.push(source);
+        Ok(())
+    }
+
+    /// Exit the innermost build file
+    pub fn pop_file(&mut self) {
+        self.files.pop();
+    }
+
+    /// Resolve `source` relative to the location of the innermost build file
+    pub fn relative_source(&self, source: Source) -> Source {
+        match self.files.last() {
+            Some(file) => file.join(source),
+            None => source,
         }
     }
 
     pub fn parse_str(&self, input: &str) -> Result<String> {
-        parse::replace(input, |key| self.variables.try_get(&key))
+        parse::replace(input, |key| lookup(&self.variables, &self.facts, key))
     }
 
     /// Replace '~' with home directory and resolve variables
     pub fn parse_path(&self, input: &str) -> Result<String> {
-        parse::replace(input, |key| self.variables.try_get(&key))
-            .map(|s| s.replace('~', &self.home_dir))
+        self.parse_str(input).map(|s| self.expand_home(&s))
+    }
+
+    /// Replace '~' with home directory
+    pub fn expand_home(&self, input: &str) -> String {
+        input.replace('~', &self.home_dir)
     }
 }
 
@@ -49,21 +171,41 @@ pub struct Locale {
     user: String,
     platform: String,
     distro: String,
+    distro_version: String,
+    arch: String,
+    hostname: String,
+    kernel: String,
 }
 
 impl Locale {
-    pub fn with_overrides(
-        user: Option<String>,
-        platform: Option<String>,
-        distro: Option<String>,
-    ) -> Self {
+    /// Detect the local environment, preferring the values set in `overrides`
+    pub fn with_overrides(overrides: LocaleSpec<String>) -> Self {
         Self {
-            user: user.unwrap_or_else(Self::get_user),
-            platform: platform.unwrap_or_else(Self::get_platform),
-            distro: distro.unwrap_or_else(Self::get_distro),
+            user: overrides.user.unwrap_or_else(Self::get_user),
+            platform: overrides.platform.unwrap_or_else(Self::get_platform),
+            distro: overrides.distro.unwrap_or_else(Self::get_distro),
+            distro_version: overrides
+                .distro_version
+                .unwrap_or_else(Self::get_distro_version),
+            arch: overrides.arch.unwrap_or_else(Self::get_arch),
+            hostname: overrides.hostname.unwrap_or_else(Self::get_hostname),
+            kernel: overrides.kernel.unwrap_or_else(Self::get_kernel),
         }
     }
 
+    /// Attributes exposed as `${{ locale.<attr> }}`
+    pub fn attrs(&self) -> [(&'static str, &str); 7] {
+        [
+            ("user", &self.user),
+            ("platform", &self.platform),
+            ("distro", &self.distro),
+            ("distro_version", &self.distro_version),
+            ("arch", &self.arch),
+            ("hostname", &self.hostname),
+            ("kernel", &self.kernel),
+        ]
+    }
+
     #[inline]
     fn get_user() -> String {
         whoami::username()
@@ -83,39 +225,139 @@ impl Locale {
             .to_string()
             .to_lowercase()
     }
+
+    /// `VERSION_ID` from `/etc/os-release`, falling back to the version reported by the OS
+    fn get_distro_version() -> String {
+        fs::read_to_string("/etc/os-release")
+            .ok()
+            .and_then(|release| Self::parse_os_release(&release, "VERSION_ID"))
+            .unwrap_or_else(|| Self::parse_distro_version(&whoami::distro()))
+    }
+
+    /// First version-like word (starting with a digit) of a distro name like `Ubuntu 22.04.4 LTS`
+    fn parse_distro_version(distro: &str) -> String {
+        distro
+            .split_whitespace()
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Value of `key` in the contents of an `os-release` file
+    fn parse_os_release(release: &str, key: &str) -> Option<String> {
+        release.lines().find_map(|line| {
+            line.strip_prefix(key)
+                .and_then(|rest| rest.strip_prefix('='))
+                .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+        })
+    }
+
+    #[inline]
+    fn get_arch() -> String {
+        env::consts::ARCH.to_string()
+    }
+
+    #[inline]
+    fn get_hostname() -> String {
+        whoami::fallible::hostname()
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    /// Kernel release (`uname -r`)
+    #[cfg(unix)]
+    fn get_kernel() -> String {
+        let mut name = std::mem::MaybeUninit::<libc::utsname>::zeroed();
+        // SAFETY: `uname` fills the struct with nul-terminated strings on success
+        unsafe {
+            if libc::uname(name.as_mut_ptr()) != 0 {
+                return String::new();
+            }
+            std::ffi::CStr::from_ptr(name.assume_init_ref().release.as_ptr())
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+
+    /// Kernel release is not reported on this platform
+    #[cfg(not(unix))]
+    fn get_kernel() -> String {
+        String::new()
+    }
+}
+
+impl ObjectKey for Locale {
+    const OBJECT_NAME: &'static str = "locale";
 }
 
 impl Default for Locale {
     fn default() -> Self {
-        Self::with_overrides(None, None, None)
+        Self::with_overrides(LocaleSpec::default())
     }
 }
 
+/// Expected value of a locale attribute
 #[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct LocaleSpec {
+#[serde(untagged)]
+pub enum LocaleValue {
+    /// Single [pattern][Matcher]
+    One(Matcher),
+    /// List of alternative [patterns][Matcher]
+    Any(Vec<Matcher>),
+}
+
+impl LocaleValue {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Self::One(matcher) => matcher.matches(value),
+            Self::Any(matchers) => matchers.iter().any(|matcher| matcher.matches(value)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct LocaleSpec<T = LocaleValue> {
     #[serde(skip_serializing_if = "Option::is_none")]
-    user: Option<String>,
+    pub user: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    platform: Option<String>,
+    pub platform: Option<T>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    distro: Option<String>,
+    pub distro: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distro_version: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<T>,
+}
+
+impl<T> LocaleSpec<T> {
+    /// Expected values in the same order as [`Locale::attrs`]
+    fn attrs(&self) -> [Option<&T>; 7] {
+        [
+            self.user.as_ref(),
+            self.platform.as_ref(),
+            self.distro.as_ref(),
+            self.distro_version.as_ref(),
+            self.arch.as_ref(),
+            self.hostname.as_ref(),
+            self.kernel.as_ref(),
+        ]
+    }
 }
 
 impl LocaleSpec {
     pub fn matches(&self, locale: &Locale) -> bool {
-        match self {
-            Self { user: Some(u), .. } if u != &locale.user => false,
-            Self {
-                platform: Some(p), ..
-            } if p != &locale.platform => false,
-            Self {
-                distro: Some(d), ..
-            } if d != &locale.distro => false,
-            _ => true,
-        }
+        self.attrs()
+            .into_iter()
+            .zip(locale.attrs())
+            .all(|(expected, (_, actual))| expected.is_none_or(|expected| expected.matches(actual)))
     }
 }
 
+#[allow(clippy::non_std_lazy_statics)]
 pub mod parse {
     use anyhow::{anyhow, Context as _, Result};
     use lazy_static::lazy_static;
@@ -410,6 +652,19 @@ pub mod tests {
         );
     }
 
+    #[test]
+    #[cfg(unix)]
+    fn kernel_release() {
+        let output = std::process::Command::new("uname")
+            .arg("-r")
+            .output()
+            .expect("Failed to run uname");
+        assert_eq!(
+            Locale::get_kernel(),
+            String::from_utf8_lossy(&output.stdout).trim()
+        );
+    }
+
     #[inline]
     fn parse_locale(args: &[&str]) -> Locale {
         YurtArgs::try_parse_from(args)
@@ -436,12 +691,56 @@ pub mod tests {
     }
 
     #[test]
-    fn locale_matching() {
-        let locale = Locale::with_overrides(
-            Some("u".to_string()),
-            Some("p".to_string()),
-            Some("d".to_string()),
+    fn override_facts() {
+        let locale = parse_locale(&[
+            "yurt",
+            "--override-distro-version",
+            "22.04",
+            "--override-arch",
+            "aarch64",
+            "--override-hostname",
+            "build-box",
+            "--override-kernel",
+            "6.8.0",
+            "show",
+        ]);
+        assert_eq!(locale.distro_version, "22.04");
+        assert_eq!(locale.arch, "aarch64");
+        assert_eq!(locale.hostname, "build-box");
+        assert_eq!(locale.kernel, "6.8.0");
+    }
+
+    #[test]
+    fn os_release_version() {
+        let release = "NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nVERSION=\"22.04.4 LTS\"\n";
+        assert_eq!(
+            Locale::parse_os_release(release, "VERSION_ID").as_deref(),
+            Some("22.04")
+        );
+        assert_eq!(Locale::parse_os_release("ID=arch\n", "VERSION_ID"), None);
+    }
+
+    #[test]
+    fn distro_version_fallback() {
+        assert_eq!(
+            Locale::parse_distro_version("Ubuntu 22.04.4 LTS"),
+            "22.04.4"
         );
+        assert_eq!(Locale::parse_distro_version("macOS 14.5"), "14.5");
+        assert_eq!(Locale::parse_distro_version("Arch Linux"), "");
+    }
+
+    #[test]
+    fn locale_matching() {
+        let locale = Locale::with_overrides(LocaleSpec::<String> {
+            user: Some("u".to_string()),
+            platform: Some("p".to_string()),
+            distro: Some("d".to_string()),
+            distro_version: Some("1.0".to_string()),
+            arch: Some("a".to_string()),
+            hostname: Some("h".to_string()),
+            kernel: Some("k".to_string()),
+        });
         let cases = [
             ("{}", true),
             ("{ user: u }", true),
@@ -452,6 +751,15 @@ pub mod tests {
             ("{ platform: _ }", false),
             ("{ user: u, distro: _ }", false),
             ("{ user: u, distro: d, platform: _ }", false),
+            ("{ arch: a, hostname: h }", true),
+            ("{ distro: d, distro_version: \"1.0\", kernel: k }", true),
+            ("{ arch: _ }", false),
+            ("{ hostname: h, distro_version: \"2.0\" }", false),
+            ("{ hostname: \"h*\", user: /^u$/ }", true),
+            ("{ distro: [x, d], distro_version: \">=1.0, <2\" }", true),
+            ("{ distro: [x, y] }", false),
+            ("{ distro_version: \">1.0\" }", false),
+            ("{ user: /^x/ }", false),
         ];
         for (yaml, result) in cases {
             let spec: LocaleSpec = serde_yaml::from_str(yaml).expect("Deserialization failed");
@@ -470,6 +778,42 @@ pub mod tests {
         assert_eq!(context.parse_str("${{ env:key }}").unwrap(), "env_value");
     }
 
+    #[test]
+    fn parse_locale_attrs() {
+        let context = Context::new(Locale::with_overrides(LocaleSpec::<String> {
+            user: Some("u".to_string()),
+            platform: Some("p".to_string()),
+            distro: Some("d".to_string()),
+            ..LocaleSpec::default()
+        }));
+        assert_eq!(
+            context
+                .parse_str("${{ locale.user }}/${{ locale.platform }}/${{ locale.distro }}")
+                .unwrap(),
+            "u/p/d"
+        );
+    }
+
+    #[test]
+    fn parse_fact() {
+        let mut context = Context::default();
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let count = dir.path().join("count");
+        context.add_fact(
+            "runs".to_string(),
+            ShellCommand::from(format!("echo run >> {count:?}; wc -l < {count:?}")),
+        );
+        assert_eq!(context.parse_str("${{ fact.runs }}").unwrap(), "1");
+        assert_eq!(context.fact("runs").unwrap(), "1");
+        // Copies of the context share the output
+        assert_eq!(context.clone().fact("runs").unwrap(), "1");
+        assert_eq!(
+            context.capture().parse_str("${{ fact.runs }}").unwrap(),
+            "1"
+        );
+        assert!(context.parse_str("${{ fact.missing }}").is_err());
+    }
+
     #[test]
     fn parse_str_invalid() {
         let mut context = Context::default();
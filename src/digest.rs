@@ -0,0 +1,106 @@
+use anyhow::{Context as _, Result};
+use ring::digest::{digest, SHA256};
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Hex encoded SHA-256 digest of `bytes`
+pub fn sha256(bytes: &[u8]) -> String {
+    digest(&SHA256, bytes)
+        .as_ref()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Hex encoded SHA-256 digest of the file at `path`
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    fs::read(path)
+        .map(|bytes| sha256(&bytes))
+        .with_context(|| format!("Failed to read file for hashing: {}", path.display()))
+}
+
+/// Hex encoded SHA-256 digest of the file or directory tree at `path`.
+///
+/// Directories are hashed from the relative path and digest of every file they contain.
+/// Symbolic links are hashed by the path they point to.
+pub fn sha256_path<P: AsRef<Path>>(path: P) -> Result<String> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return sha256_file(path);
+    }
+    let mut files = Vec::new();
+    walk(path, &mut files)?;
+    files.sort();
+    let mut manifest = String::new();
+    for file in files {
+        let relative = file.strip_prefix(path).unwrap_or(&file);
+        let hash = match file.read_link() {
+            Ok(link) => sha256(link.as_os_str().as_encoded_bytes()),
+            Err(_) => sha256_file(&file)?,
+        };
+        let _ = writeln!(manifest, "{hash} {}", relative.display());
+    }
+    Ok(sha256(manifest.as_bytes()))
+}
+
+/// Collect every file below `dir` (symbolic links are not followed)
+pub fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            walk(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_known() {
+        assert_eq!(
+            sha256(b"hello world"),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn sha256_file_missing() {
+        assert!(sha256_file("some/missing/file").is_err());
+    }
+
+    #[test]
+    fn sha256_tree() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("nested").join("file"), "content").unwrap();
+        let first = sha256_path(dir.path()).expect("Failed to hash tree");
+        assert_eq!(sha256_path(dir.path()).unwrap(), first);
+        fs::write(dir.path().join("nested").join("file"), "changed").unwrap();
+        assert_ne!(sha256_path(dir.path()).unwrap(), first);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sha256_tree_cycle() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("nested").join("cycle")).unwrap();
+        let first = sha256_path(dir.path()).expect("Failed to hash tree");
+        fs::remove_file(dir.path().join("nested").join("cycle")).unwrap();
+        std::os::unix::fs::symlink("other", dir.path().join("nested").join("cycle")).unwrap();
+        assert_ne!(sha256_path(dir.path()).unwrap(), first);
+    }
+}
@@ -44,11 +44,21 @@ pub mod tests {
 
     test_case!(config, Config);
     test_case!(case, BuildSpec);
+    test_case!(chmod, BuildSpec);
+    test_case!(directory, BuildSpec);
+    test_case!(download, BuildSpec);
+    test_case!(file, BuildSpec);
+    test_case!(github_release, BuildSpec);
     test_case!(hook, BuildSpec);
+    test_case!(include, BuildSpec);
+    test_case!(line_in_file, BuildSpec);
     test_case!(link, BuildSpec);
+    test_case!(link_tree, BuildSpec);
     test_case!(matrix, BuildSpec);
+    test_case!(merge, BuildSpec);
     test_case!(package_manager, BuildSpec);
     test_case!(package, BuildSpec);
     test_case!(repo, BuildSpec);
+    test_case!(template, BuildSpec);
     test_case!(vars, BuildSpec);
 }
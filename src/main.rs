@@ -5,22 +5,28 @@
     clippy::module_name_repetitions,
     clippy::single_match_else
 )]
+mod backup;
 mod config;
 mod context;
+mod digest;
 mod docs;
+mod matcher;
+mod source;
 mod specs;
+mod state;
 
 use self::{
-    config::{Config, ResolvedConfig},
-    context::{Context, Locale},
-    specs::{BuildUnit, BuildUnitKind, Hook},
+    config::{Config, ResolvedConfig, Summary},
+    context::{Context, Locale, LocaleSpec},
+    specs::{BuildSpec, BuildUnit, BuildUnitKind, Hook, Link, Plan, Resolve},
+    state::{Stamps, State},
 };
 use anyhow::{bail, Context as _, Result};
-use clap::{command, ArgGroup, Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 use std::{
     env,
     io::{self, Write},
-    path::PathBuf,
+    path::{self, Path, PathBuf},
     time::Instant,
 };
 
@@ -45,20 +51,67 @@ enum YurtAction {
         hook: Option<Hook>,
     },
 
+    /// Report the current state of each resolved unit
+    Status,
+
     /// Install the resolved build
     Install {
-        /// Clean link target conflicts
+        /// Back up files that conflict with link sources
         #[arg(long, short)]
         clean: bool,
+
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+
+        /// Revert previously installed units that are no longer in the build
+        #[arg(long)]
+        prune: bool,
     },
 
     /// Uninstall the resolved build
-    Uninstall,
+    Uninstall {
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+
+    /// Revert previously installed units that are no longer in the build
+    Prune {
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+
+    /// Restore files that were backed up by `install --clean`
+    Restore {
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
+
+    /// Move an existing file into the dotfiles repo and link it back
+    Adopt {
+        /// Path of the file or directory to adopt
+        path: PathBuf,
+
+        /// Destination path (or directory) in the repo; supports `${{ }}` keys
+        #[arg(long, value_name = "REPO_PATH")]
+        into: String,
+
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
+    },
 
     /// Run resolved build hooks
     Hook {
         /// Type of hook to run
         hook: Hook,
+
+        /// Print the planned changes without applying them
+        #[arg(long, short = 'n')]
+        dry_run: bool,
     },
 }
 
@@ -74,10 +127,14 @@ pub struct YurtArgs {
     #[arg(long, short = 'f', value_name = "FILE")]
     file: Option<PathBuf>,
 
-    /// YAML build file URL
+    /// YAML build file URL (`https://...` or `git+https://host/repo.git#path@ref`)
     #[arg(long, short = 'u', value_name = "URL")]
     file_url: Option<String>,
 
+    /// YAML state file path
+    #[arg(long, value_name = "FILE")]
+    state_file: Option<PathBuf>,
+
     /// Logging level
     #[arg(long)]
     log: Option<String>,
@@ -86,6 +143,10 @@ pub struct YurtArgs {
     #[arg(long)]
     root: bool,
 
+    /// Run every unit and summarize failures instead of stopping at the first error
+    #[arg(long, short = 'k')]
+    keep_going: bool,
+
     /// Override target username
     #[arg(long, value_name = "USER")]
     override_user: Option<String>,
@@ -98,6 +159,22 @@ pub struct YurtArgs {
     #[arg(long, value_name = "DISTRO")]
     override_distro: Option<String>,
 
+    /// Override target distro version
+    #[arg(long, value_name = "VERSION")]
+    override_distro_version: Option<String>,
+
+    /// Override target architecture
+    #[arg(long, value_name = "ARCH")]
+    override_arch: Option<String>,
+
+    /// Override target hostname
+    #[arg(long, value_name = "HOSTNAME")]
+    override_hostname: Option<String>,
+
+    /// Override target kernel release
+    #[arg(long, value_name = "KERNEL")]
+    override_kernel: Option<String>,
+
     /// Include only the specified build unit types
     #[arg(
         value_enum,
@@ -124,15 +201,21 @@ pub struct YurtArgs {
 
 impl YurtArgs {
     fn get_locale(&self) -> Locale {
-        Locale::with_overrides(
-            self.override_user.clone(),
-            self.override_platform.clone(),
-            self.override_distro.clone(),
-        )
+        Locale::with_overrides(LocaleSpec {
+            user: self.override_user.clone(),
+            platform: self.override_platform.clone(),
+            distro: self.override_distro.clone(),
+            distro_version: self.override_distro_version.clone(),
+            arch: self.override_arch.clone(),
+            hostname: self.override_hostname.clone(),
+            kernel: self.override_kernel.clone(),
+        })
     }
 
-    fn get_context(&self) -> Context {
-        Context::new(self.get_locale())
+    fn get_context(&self) -> Result<Context> {
+        let mut context = Context::new(self.get_locale());
+        context.stamps = Stamps::for_state_file(&self.get_state_path()?);
+        Ok(context)
     }
 
     fn get_config(&self) -> Result<Config> {
@@ -147,20 +230,234 @@ impl YurtArgs {
 
     fn get_resolved_config(&self) -> Result<ResolvedConfig> {
         self.get_config()
-            .and_then(|config| config.resolve(self.get_context()))
-            .map(|resolved| {
-                resolved
-                    .filter(|unit, _| {
-                        self.include
-                            .as_ref()
-                            .map_or(true, |kinds| unit.included_in(kinds))
-                    })
-                    .filter(|unit, _| {
-                        self.exclude
-                            .as_ref()
-                            .map_or(true, |kinds| !unit.included_in(kinds))
-                    })
+            .and_then(|config| config.resolve(self.get_context()?))
+            .map(|resolved| resolved.filter(|unit, _| self.includes(unit)))
+    }
+
+    /// Check `unit` against the `--include` and `--exclude` filters
+    #[allow(clippy::unnecessary_map_or)]
+    fn includes(&self, unit: &BuildUnit) -> bool {
+        self.include
+            .as_ref()
+            .map_or(true, |kinds| unit.included_in(kinds))
+            && self
+                .exclude
+                .as_ref()
+                .map_or(true, |kinds| !unit.included_in(kinds))
+    }
+
+    fn get_state_path(&self) -> Result<PathBuf> {
+        match self.state_file {
+            Some(ref file) => Ok(file.clone()),
+            None => State::default_path(),
+        }
+    }
+
+    /// Record `result` in `summary` if `--keep-going` is set, otherwise return it
+    fn check(&self, summary: &mut Summary, unit: &BuildUnit, result: Result<()>) -> Result<()> {
+        match self.keep_going {
+            true => {
+                summary.record(unit, result);
+                Ok(())
+            }
+            false => result.map(|()| summary.record(unit, Ok(()))),
+        }
+    }
+
+    /// Call `f` on each unit that is affected by `hook`
+    fn run_units<F>(
+        &self,
+        build: &ResolvedConfig,
+        hook: &Hook,
+        summary: &mut Summary,
+        mut f: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&BuildUnit, &Context) -> Result<()>,
+    {
+        build.for_each_unit(|unit, context| match unit.affected_by(hook) {
+            true => self.check(summary, unit, f(unit, context)),
+            false => {
+                summary.skip();
+                Ok(())
+            }
+        })
+    }
+
+    /// Print `summary` if `--keep-going` is set and fail if any unit failed
+    fn finish(&self, summary: Summary) -> Result<()> {
+        if self.keep_going {
+            writeln!(io::stdout(), "{summary}").context("Failed to write summary to stdout")?;
+        }
+        summary.into_result()
+    }
+
+    /// Revert units recorded in `state` that are missing from `build`
+    fn prune(
+        &self,
+        build: &ResolvedConfig,
+        state: &mut State,
+        dry_run: bool,
+        summary: &mut Summary,
+    ) -> Result<()> {
+        let mut context = build.context.clone();
+        state.extend_context(&mut context);
+        for mut unit in state.stale(build) {
+            if !self.includes(&unit) {
+                continue;
+            }
+            unit.set_stamps(&context.stamps);
+            if dry_run {
+                write_plan(&unit, &unit.plan_uninstall(&context))?;
+                continue;
+            }
+            log::info!("Pruning {unit}");
+            let result = unit.uninstall(&context);
+            if result.is_ok() {
+                state.forget(&unit);
+            }
+            self.check(summary, &unit, result)?;
+        }
+        Ok(())
+    }
+
+    fn install(&self, clean: bool, dry_run: bool, prune: bool) -> Result<()> {
+        let build = self.get_resolved_config()?;
+        let state_path = self.get_state_path()?;
+        let mut state = State::from_path(&state_path)?;
+        let mut summary = Summary::default();
+        if dry_run {
+            if prune {
+                self.prune(&build, &mut state, true, &mut summary)?;
+            }
+            return build.for_each_unit(|unit, context| {
+                write_plan(unit, &unit.plan_install(context, clean))
+            });
+        }
+        let result = if prune {
+            self.prune(&build, &mut state, false, &mut summary)
+        } else {
+            Ok(())
+        }
+        .and_then(|()| {
+            self.run_units(&build, &Hook::Install, &mut summary, |unit, context| {
+                let plan = unit.plan_install(context, clean);
+                unit.install(context, clean)?;
+                // Units that were already satisfied were not installed by yurt
+                match plan {
+                    Plan::Skip | Plan::Satisfied => state.update(unit),
+                    _ => state.record(unit),
+                }
+                Ok(())
             })
+        });
+        state
+            .save(&state_path)
+            .and(result)
+            .and_then(|()| self.finish(summary))
+    }
+
+    fn status(&self) -> Result<()> {
+        let build = self.get_resolved_config()?;
+        let mut rows = Vec::new();
+        build.for_each_unit(|unit, context| {
+            rows.push((unit.to_string(), unit.health(context)));
+            Ok(())
+        })?;
+        let width = rows.iter().map(|(unit, _)| unit.len()).max().unwrap_or(0);
+        let mut stdout = io::stdout().lock();
+        for (unit, health) in &rows {
+            let row = format!("{:<11} {unit:<width$}  {}", health.label(), health.detail());
+            writeln!(stdout, "{}", row.trim_end()).context("Failed to write status to stdout")?;
+        }
+        match rows
+            .iter()
+            .filter(|(_, health)| health.is_unsatisfied())
+            .count()
+        {
+            0 => Ok(()),
+            count => bail!("{count} of {} units are unsatisfied", rows.len()),
+        }
+    }
+
+    fn uninstall(&self, dry_run: bool) -> Result<()> {
+        let build = self.get_resolved_config()?;
+        if dry_run {
+            return build
+                .for_each_unit(|unit, context| write_plan(unit, &unit.plan_uninstall(context)));
+        }
+        let state_path = self.get_state_path()?;
+        let mut state = State::from_path(&state_path)?;
+        let mut summary = Summary::default();
+        let result = self.run_units(&build, &Hook::Uninstall, &mut summary, |unit, context| {
+            unit.uninstall(context)?;
+            state.forget(unit);
+            Ok(())
+        });
+        state
+            .save(&state_path)
+            .and(result)
+            .and_then(|()| self.finish(summary))
+    }
+
+    fn restore(&self, dry_run: bool) -> Result<()> {
+        let build = self.get_resolved_config()?;
+        if dry_run {
+            return build.for_each_unit(|unit, _| write_plan(unit, &unit.plan_restore()));
+        }
+        let state_path = self.get_state_path()?;
+        let mut state = State::from_path(&state_path)?;
+        let mut summary = Summary::default();
+        let result = build.for_each_unit(|unit, _| match unit.plan_restore() {
+            Plan::Skip | Plan::Satisfied => {
+                summary.skip();
+                Ok(())
+            }
+            _ => {
+                let result = unit.restore();
+                if result.is_ok() {
+                    state.forget(unit);
+                }
+                self.check(&mut summary, unit, result)
+            }
+        });
+        state
+            .save(&state_path)
+            .and(result)
+            .and_then(|()| self.finish(summary))
+    }
+
+    fn adopt(&self, path: &Path, into: &str, dry_run: bool) -> Result<()> {
+        let build = self.get_resolved_config()?;
+        let source = path::absolute(path).context("Failed to resolve adopted path")?;
+        let name = source
+            .file_name()
+            .context("Adopted path has no file name")?
+            .to_string_lossy();
+        let target =
+            match into.ends_with('/') || Path::new(&build.context.parse_path(into)?).is_dir() {
+                true => format!("{}/{name}", into.trim_end_matches('/')),
+                false => into.to_string(),
+            };
+        let source = match dirs::home_dir().and_then(|home| source.strip_prefix(home).ok()) {
+            Some(relative) => Path::new("~").join(relative),
+            None => source.clone(),
+        };
+        let spec = Link::new(source, target);
+        let unit = spec.clone().resolve(&mut build.context.clone())?;
+        let BuildUnit::Link(ref link) = unit else {
+            unreachable!("Link resolves to a link unit");
+        };
+        if dry_run {
+            return write_plan(&unit, &link.plan_adopt());
+        }
+        let state_path = self.get_state_path()?;
+        let mut state = State::from_path(&state_path)?;
+        link.adopt()?;
+        state.record(&unit);
+        state.save(&state_path)?;
+        let config = Config::from_build(vec![BuildSpec::Link(spec)]);
+        write!(io::stdout(), "{}", config.yaml()?).context("Failed to write yaml to stdout")
     }
 
     fn execute(&self) -> Result<()> {
@@ -170,7 +467,7 @@ impl YurtArgs {
                 raw, context: true, ..
             } => {
                 let context = if raw {
-                    self.get_context()
+                    self.get_context()?
                 } else {
                     self.get_resolved_config()?.context
                 };
@@ -188,26 +485,7 @@ impl YurtArgs {
                     let resolved = self.get_resolved_config()?;
                     if let Some(hook_arg) = hook_arg {
                         resolved
-                            .filter(|unit, context| {
-                                let expect = matches!(hook_arg, Hook::Install);
-                                match hook_arg {
-                                    Hook::Install | Hook::Uninstall => match unit {
-                                        BuildUnit::Repo(repo) => repo.is_available() != expect,
-                                        BuildUnit::Link(link) => link.is_valid() != expect,
-                                        BuildUnit::Package(package) => {
-                                            package.is_installed(context) != expect
-                                        }
-                                        BuildUnit::PackageManager(manager) => {
-                                            manager.is_available() != expect
-                                        }
-                                        BuildUnit::Hook(hook) => hook.applies(hook_arg),
-                                    },
-                                    Hook::Custom(_) => match unit {
-                                        BuildUnit::Hook(hook) => hook.applies(hook_arg),
-                                        _ => false,
-                                    },
-                                }
-                            })
+                            .filter(|unit, context| hook_filter(unit, context, hook_arg))
                             .into_config()
                     } else {
                         resolved.into_config()
@@ -216,36 +494,89 @@ impl YurtArgs {
                 writeln!(io::stdout(), "{}", config.yaml()?)
                     .context("Failed to write yaml to stdout")
             }
+            // $ yurt status
+            YurtAction::Status => self.status(),
             // $ yurt install
-            YurtAction::Install { clean } => self.get_resolved_config().and_then(|build| {
-                build.for_each_unit(|unit, context| match unit {
-                    BuildUnit::Repo(repo) => repo.require().map(drop),
-                    BuildUnit::Link(link) => link.link(clean),
-                    BuildUnit::Hook(hook) => hook.exec_for(&Hook::Install),
-                    BuildUnit::Package(package) => package.install(context),
-                    BuildUnit::PackageManager(manager) => manager.require(),
-                })
-            }),
+            YurtAction::Install {
+                clean,
+                dry_run,
+                prune,
+            } => self.install(clean, dry_run, prune),
             // $ yurt uninstall
-            YurtAction::Uninstall => self.get_resolved_config().and_then(|build| {
-                build.for_each_unit(|unit, context| match unit {
-                    BuildUnit::Link(link) => link.unlink(),
-                    BuildUnit::Hook(hook) => hook.exec_for(&Hook::Uninstall),
-                    BuildUnit::Package(package) => package.uninstall(context),
-                    _ => Ok(()),
-                })
-            }),
+            YurtAction::Uninstall { dry_run } => self.uninstall(dry_run),
+            // $ yurt prune
+            YurtAction::Prune { dry_run } => {
+                let build = self.get_resolved_config()?;
+                let state_path = self.get_state_path()?;
+                let mut state = State::from_path(&state_path)?;
+                let mut summary = Summary::default();
+                let result = self.prune(&build, &mut state, dry_run, &mut summary);
+                match dry_run {
+                    true => result,
+                    false => state
+                        .save(&state_path)
+                        .and(result)
+                        .and_then(|()| self.finish(summary)),
+                }
+            }
+            // $ yurt restore
+            YurtAction::Restore { dry_run } => self.restore(dry_run),
+            // $ yurt adopt
+            YurtAction::Adopt {
+                ref path,
+                ref into,
+                dry_run,
+            } => self.adopt(path, into, dry_run),
             // $ yurt hook
-            YurtAction::Hook { hook: ref arg } => self.get_resolved_config().and_then(|build| {
-                build.for_each_unit(|unit, _| match unit {
-                    BuildUnit::Hook(hook) => hook.exec_for(arg),
-                    _ => Ok(()),
-                })
+            YurtAction::Hook {
+                hook: ref arg,
+                dry_run,
+            } => self.get_resolved_config().and_then(|build| match dry_run {
+                true => build.for_each_unit(|unit, _| write_plan(unit, &unit.plan_hook(arg))),
+                false => {
+                    let mut summary = Summary::default();
+                    self.run_units(&build, arg, &mut summary, |unit, _| unit.hook(arg))?;
+                    self.finish(summary)
+                }
             }),
         }
     }
 }
 
+/// Return true if `unit` would be affected by running `hook`
+fn hook_filter(unit: &BuildUnit, context: &Context, hook: &Hook) -> bool {
+    let expect = matches!(hook, Hook::Install);
+    match hook {
+        Hook::Install | Hook::Uninstall => match unit {
+            BuildUnit::Repo(repo) => repo.is_available() != expect,
+            BuildUnit::Link(link) => link.is_valid() != expect,
+            BuildUnit::Package(package) => package.is_installed(context) != expect,
+            BuildUnit::PackageManager(manager) => manager.is_available() != expect,
+            BuildUnit::Template(template) => template.is_current() != expect,
+            BuildUnit::Chmod(chmod) => chmod.is_applied() != expect,
+            BuildUnit::Directory(directory) => directory.is_created() != expect,
+            BuildUnit::File(file) => file.is_current() != expect,
+            BuildUnit::LineInFile(lines) => lines.is_present() != expect,
+            BuildUnit::Merge(merge) => merge.is_merged() != expect,
+            BuildUnit::Download(download) => download.is_installed() != expect,
+            BuildUnit::GithubRelease(release) => release.is_installed() != expect,
+            BuildUnit::Hook(shell) => shell.applies(hook),
+        },
+        Hook::Custom(_) => match unit {
+            BuildUnit::Hook(shell) => shell.applies(hook),
+            _ => false,
+        },
+    }
+}
+
+/// Print the planned outcome for `unit` (units that are skipped are omitted)
+fn write_plan(unit: &BuildUnit, plan: &Plan) -> Result<()> {
+    match plan {
+        Plan::Skip => Ok(()),
+        _ => writeln!(io::stdout(), "{unit}: {plan}").context("Failed to write plan to stdout"),
+    }
+}
+
 #[doc(hidden)]
 fn main() -> Result<()> {
     let timer = Instant::now();
@@ -0,0 +1,192 @@
+use anyhow::{Context as _, Result};
+use regex::Regex;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Parse the leading dotted number of `input` as a version.
+///
+/// Missing components are zero and leading zeros are ignored, so `22.04` is `22.4.0`
+/// and `6.8.0-45-generic` is `6.8.0`.
+pub fn version(input: &str) -> Option<Version> {
+    let numeric: String = input
+        .trim()
+        .trim_start_matches('v')
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let mut parts = numeric.split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().transpose().ok()?.unwrap_or(0);
+    let patch = parts.next().transpose().ok()?.unwrap_or(0);
+    Some(Version::new(major, minor, patch))
+}
+
+/// Parse a comma separated version requirement (`>=22.04, <24.04`) with lenient versions.
+///
+/// Leading zeros are ignored but the number of components is kept, so `<=22.04` is
+/// `<=22.4` and matches any `22.4.x`.
+pub fn version_req(input: &str) -> Result<VersionReq> {
+    let comparators = input
+        .split(',')
+        .map(|comparator| {
+            let comparator = comparator.trim();
+            let start = comparator
+                .find(|c: char| c.is_ascii_digit())
+                .with_context(|| format!("Invalid version comparator: {comparator}"))?;
+            let (op, rest) = comparator.split_at(start);
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let (numeric, suffix) = rest.split_at(end);
+            let numeric = numeric
+                .split('.')
+                .map(|part| match part {
+                    "" => Ok(String::new()),
+                    part => part.parse::<u64>().map(|part| part.to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Invalid version comparator: {comparator}"))?;
+            Ok(format!("{}{}{suffix}", op.trim(), numeric.join(".")))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    VersionReq::parse(&comparators.join(", "))
+        .with_context(|| format!("Invalid version requirement: {input}"))
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    /// Plain string
+    Exact,
+    /// Glob pattern (`ci-*`)
+    Glob(glob::Pattern),
+    /// Regular expression (`/^svc_/`)
+    Regex(Regex),
+    /// Version requirement (`>=22.04`)
+    Version(VersionReq),
+}
+
+/// String pattern that matches exactly, by glob, by `/regex/`, or by version requirement.
+///
+/// Values starting with `<`, `>`, `=`, `^`, or `~` are version requirements and values
+/// containing `*`, `?`, or `[` are glob patterns. A value that contains these characters
+/// literally must be written as an anchored regex to match exactly (`/^host\[1\]$/`).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Matcher {
+    pattern: String,
+    kind: Kind,
+}
+
+impl Matcher {
+    pub fn matches(&self, value: &str) -> bool {
+        match self.kind {
+            Kind::Exact => self.pattern == value,
+            Kind::Glob(ref glob) => glob.matches(value),
+            Kind::Regex(ref regex) => regex.is_match(value),
+            Kind::Version(ref req) => version(value).is_some_and(|version| req.matches(&version)),
+        }
+    }
+}
+
+impl TryFrom<String> for Matcher {
+    type Error = anyhow::Error;
+
+    fn try_from(pattern: String) -> Result<Self> {
+        let kind = if let Some(regex) = pattern
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+        {
+            Kind::Regex(Regex::new(regex).with_context(|| format!("Invalid regex: {pattern}"))?)
+        } else if pattern.starts_with(['<', '>', '=', '^', '~']) {
+            Kind::Version(version_req(&pattern)?)
+        } else if pattern.contains(['*', '?', '[']) {
+            Kind::Glob(
+                glob::Pattern::new(&pattern)
+                    .with_context(|| format!("Invalid glob pattern: {pattern}"))?,
+            )
+        } else {
+            Kind::Exact
+        };
+        Ok(Self { pattern, kind })
+    }
+}
+
+impl From<Matcher> for String {
+    fn from(matcher: Matcher) -> Self {
+        matcher.pattern
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(pattern: &str) -> Matcher {
+        Matcher::try_from(pattern.to_string()).expect("Failed to parse pattern")
+    }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(version("22.04"), Some(Version::new(22, 4, 0)));
+        assert_eq!(version("v1.2.3"), Some(Version::new(1, 2, 3)));
+        assert_eq!(version("6.8.0-45-generic"), Some(Version::new(6, 8, 0)));
+        assert_eq!(version("12"), Some(Version::new(12, 0, 0)));
+        assert_eq!(version("rolling"), None);
+    }
+
+    #[test]
+    fn parse_version_req() {
+        let req = version_req(">=20.04, <24.04").unwrap();
+        assert!(req.matches(&Version::new(22, 4, 0)));
+        assert!(!req.matches(&Version::new(24, 4, 0)));
+        assert!(version_req(">=rolling").is_err());
+    }
+
+    #[test]
+    fn partial_version_req() {
+        let req = version_req("<=22.04").unwrap();
+        assert!(req.matches(&Version::new(22, 4, 5)));
+        assert!(!req.matches(&Version::new(22, 5, 0)));
+        let req = version_req("=6").unwrap();
+        assert!(req.matches(&Version::new(6, 8, 0)));
+        assert!(!req.matches(&Version::new(7, 0, 0)));
+        let req = version_req("~1").unwrap();
+        assert!(req.matches(&Version::new(1, 9, 0)));
+        assert!(!req.matches(&Version::new(2, 0, 0)));
+        let req = version_req(">=1.02.*").unwrap();
+        assert!(req.matches(&Version::new(1, 2, 0)));
+    }
+
+    #[test]
+    fn match_kinds() {
+        assert!(matcher("ubuntu").matches("ubuntu"));
+        assert!(!matcher("ubuntu").matches("ubuntu-core"));
+        assert!(matcher("ci-*").matches("ci-runner-3"));
+        assert!(!matcher("ci-*").matches("dev-box"));
+        assert!(matcher("/^svc_/").matches("svc_backup"));
+        assert!(!matcher("/^svc_/").matches("user_svc_"));
+        assert!(matcher(">=22.04").matches("22.04"));
+        assert!(!matcher(">=22.04").matches("20.04"));
+        assert!(!matcher(">=22.04").matches("rolling"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Matcher::try_from("/(/".to_string()).is_err());
+        assert!(Matcher::try_from(">=x".to_string()).is_err());
+        assert!(Matcher::try_from("[".to_string()).is_err());
+    }
+
+    #[test]
+    fn serialize_pattern() {
+        let matcher: Matcher = serde_yaml::from_str("\">=22.04\"").unwrap();
+        assert_eq!(serde_yaml::to_string(&matcher).unwrap(), "'>=22.04'\n");
+    }
+}
@@ -0,0 +1,488 @@
+use crate::digest;
+
+use anyhow::{anyhow, bail, Context as _, Error, Result};
+use git2::{build::RepoBuilder, Repository};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Location of a build file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Local file path
+    Path(PathBuf),
+    /// Remote file url (`https://host/build.yaml`)
+    Url(String),
+    /// File in a remote git repository (`git+https://host/repo.git#path/to/build.yaml@ref`)
+    Git {
+        url: String,
+        path: String,
+        reference: Option<String>,
+    },
+}
+
+impl Source {
+    /// Check if `input` refers to a remote build file
+    pub fn is_remote(input: &str) -> bool {
+        input.starts_with("git+") || input.starts_with("http://") || input.starts_with("https://")
+    }
+
+    pub fn parse(input: &str) -> Result<Self> {
+        if let Some(git) = input.strip_prefix("git+") {
+            let (url, file) = git.split_once('#').with_context(|| {
+                format!("Git source is missing a `#path` to the build file: {input}")
+            })?;
+            let (path, reference) = match file.rsplit_once('@') {
+                Some((path, reference)) => (path, Some(reference.to_string())),
+                None => (file, None),
+            };
+            if path.is_empty() {
+                bail!("Git source has an empty build file path: {input}");
+            }
+            Ok(Self::Git {
+                url: url.to_string(),
+                path: path.to_string(),
+                reference,
+            })
+        } else if Self::is_remote(input) {
+            Ok(Self::Url(input.to_string()))
+        } else {
+            Ok(Self::Path(PathBuf::from(input)))
+        }
+    }
+
+    /// Resolve `other` relative to the location of this build file.
+    ///
+    /// Only relative paths are modified; remote and absolute sources are returned as-is.
+    pub fn join(&self, other: Self) -> Self {
+        let relative = match other {
+            Self::Path(ref path) if path.is_relative() => path.to_string_lossy().to_string(),
+            _ => return other,
+        };
+        match self {
+            Self::Path(path) => Self::Path(match path.parent() {
+                Some(dir) => dir.join(relative),
+                None => PathBuf::from(relative),
+            }),
+            Self::Url(url) => match url.rsplit_once('/') {
+                Some((dir, _)) => Self::Url(join_segments(dir, &relative)),
+                None => other,
+            },
+            Self::Git {
+                url,
+                path,
+                reference,
+            } => Self::Git {
+                url: url.clone(),
+                path: match path.rsplit_once('/') {
+                    Some((dir, _)) => join_segments(dir, &relative),
+                    None => join_segments("", &relative),
+                },
+                reference: reference.clone(),
+            },
+        }
+    }
+
+    /// Canonical form used to compare sources
+    pub fn canonicalize(self) -> Self {
+        match self {
+            Self::Path(path) => Self::Path(fs::canonicalize(&path).unwrap_or(path)),
+            other => other,
+        }
+    }
+
+    /// Read the contents of the build file (remote sources are cached)
+    pub fn read(&self) -> Result<String> {
+        match self {
+            Self::Path(path) => fs::read_to_string(path).context("Failed to read build file"),
+            Self::Url(url) => Cache::from_env()?.fetch_url(url),
+            Self::Git {
+                url,
+                path,
+                reference,
+            } => Cache::from_env()?.fetch_git(url, path, reference.as_deref()),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Url(url) => write!(f, "{url}"),
+            Self::Git {
+                url,
+                path,
+                reference: Some(reference),
+            } => write!(f, "git+{url}#{path}@{reference}"),
+            Self::Git { url, path, .. } => write!(f, "git+{url}#{path}"),
+        }
+    }
+}
+
+/// Append the `/` separated `relative` path to `base`, collapsing `.` and `..` segments
+fn join_segments(base: &str, relative: &str) -> String {
+    let mut segments: Vec<&str> = base.split('/').collect();
+    if segments == [""] {
+        segments.clear();
+    }
+    for segment in relative.split(['/', '\\']) {
+        match segment {
+            "." | "" => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/// Validators stored alongside a cached remote file
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct UrlMeta {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+/// Local copies of remote build files.
+///
+/// Cached copies are used when the remote source cannot be reached.
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Default cache (`$YURT_CACHE_DIR` or `<cache dir>/yurt`)
+    pub fn from_env() -> Result<Self> {
+        if let Ok(dir) = env::var("YURT_CACHE_DIR") {
+            return Ok(Self::new(dir));
+        }
+        dirs::cache_dir()
+            .map(|dir| Self::new(dir.join("yurt")))
+            .context("Failed to locate cache directory")
+    }
+
+    /// Stable file name for `url`
+    fn key(url: &str) -> String {
+        digest::sha256(url.as_bytes())
+    }
+
+    /// Fetch `url`, revalidating the cached copy with `ETag` / `Last-Modified`
+    pub fn fetch_url(&self, url: &str) -> Result<String> {
+        let dir = self.dir.join("url");
+        let key = Self::key(url);
+        let body_path = dir.join(format!("{key}.yaml"));
+        let meta_path = dir.join(format!("{key}.meta.yaml"));
+        let meta: Option<UrlMeta> = body_path
+            .is_file()
+            .then(|| fs::read_to_string(&meta_path).ok())
+            .flatten()
+            .and_then(|meta| serde_yaml::from_str(&meta).ok());
+
+        let mut request = minreq::get(url);
+        if let Some(ref meta) = meta {
+            if let Some(ref etag) = meta.etag {
+                request = request.with_header("If-None-Match", etag);
+            }
+            if let Some(ref last_modified) = meta.last_modified {
+                request = request.with_header("If-Modified-Since", last_modified);
+            }
+        }
+        let fallback = |error: Error| -> Result<String> {
+            match fs::read_to_string(&body_path) {
+                Ok(body) => {
+                    log::warn!("{error:#}; using cached copy of {url}");
+                    Ok(body)
+                }
+                Err(_) => Err(error),
+            }
+        };
+        match request.send() {
+            Ok(response) if response.status_code == 304 && meta.is_some() => {
+                log::debug!("Cached copy is up to date: {url}");
+                fs::read_to_string(&body_path).context("Failed to read cached build file")
+            }
+            Ok(response) if (200..300).contains(&response.status_code) => {
+                let body = response
+                    .as_str()
+                    .context("Failed to decode remote build file")?
+                    .to_string();
+                let meta = UrlMeta {
+                    url: url.to_string(),
+                    etag: response.headers.get("etag").cloned(),
+                    last_modified: response.headers.get("last-modified").cloned(),
+                };
+                if let Err(error) = Self::write(&body_path, &body)
+                    .and_then(|()| Self::write(&meta_path, &serde_yaml::to_string(&meta)?))
+                {
+                    log::warn!("Failed to cache {url}: {error:#}");
+                }
+                Ok(body)
+            }
+            Ok(response) => fallback(anyhow!(
+                "Remote build file request failed: {} {}",
+                response.status_code,
+                response.reason_phrase
+            )),
+            Err(error) => fallback(anyhow!(error).context("Failed to reach remote build file")),
+        }
+    }
+
+    /// Read `path` from `url` at `reference` (default branch if unset) using a cached clone
+    pub fn fetch_git(&self, url: &str, path: &str, reference: Option<&str>) -> Result<String> {
+        let dir = self.dir.join("git").join(Self::key(url));
+        let repo = match Repository::open_bare(&dir) {
+            Ok(repo) => {
+                if let Err(error) = Self::update(&repo) {
+                    log::warn!("{error:#}; using cached clone of {url}");
+                }
+                repo
+            }
+            Err(_) => RepoBuilder::new()
+                .bare(true)
+                .clone(url, &dir)
+                .with_context(|| format!("Failed to clone git repository: {url}"))?,
+        };
+        let reference = reference.unwrap_or("HEAD");
+        let object = repo
+            .revparse_single(&format!("origin/{reference}"))
+            .or_else(|_| repo.revparse_single(reference))
+            .with_context(|| format!("Failed to find git reference: {reference}"))?;
+        let blob = object
+            .peel_to_tree()
+            .and_then(|tree| tree.get_path(Path::new(path)))
+            .and_then(|entry| entry.to_object(&repo))
+            .and_then(|object| object.peel_to_blob())
+            .with_context(|| format!("Failed to find {path} at {reference} in {url}"))?;
+        String::from_utf8(blob.content().to_vec()).context("Failed to decode git build file")
+    }
+
+    /// Fetch the latest branches and tags from `origin`
+    fn update(repo: &Repository) -> Result<()> {
+        repo.find_remote("origin")
+            .and_then(|mut remote| {
+                remote.fetch(
+                    &[
+                        "+refs/heads/*:refs/remotes/origin/*",
+                        "+refs/tags/*:refs/tags/*",
+                    ],
+                    None,
+                    None,
+                )
+            })
+            .context("Failed to fetch git repository")
+    }
+
+    fn write(path: &Path, contents: &str) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create cache directory")?;
+        }
+        fs::write(path, contents).context("Failed to write cache file")
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread::{self, JoinHandle},
+    };
+
+    /// Serve one canned HTTP response per connection, returning the raw requests
+    pub fn serve(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind listener");
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().expect("Failed to accept connection");
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (address, handle)
+    }
+
+    pub fn ok_response(headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn parse_sources() {
+        assert_eq!(
+            Source::parse("dir/build.yaml").unwrap(),
+            Source::Path(PathBuf::from("dir/build.yaml"))
+        );
+        assert_eq!(
+            Source::parse("https://host/build.yaml").unwrap(),
+            Source::Url("https://host/build.yaml".to_string())
+        );
+        assert_eq!(
+            Source::parse("git+https://host/repo.git#path/to/build.yaml@v1.0").unwrap(),
+            Source::Git {
+                url: "https://host/repo.git".to_string(),
+                path: "path/to/build.yaml".to_string(),
+                reference: Some("v1.0".to_string()),
+            }
+        );
+        assert_eq!(
+            Source::parse("git+https://host/repo.git#build.yaml").unwrap(),
+            Source::Git {
+                url: "https://host/repo.git".to_string(),
+                path: "build.yaml".to_string(),
+                reference: None,
+            }
+        );
+        assert!(Source::parse("git+https://host/repo.git").is_err());
+        assert!(Source::parse("git+https://host/repo.git#@v1").is_err());
+    }
+
+    #[test]
+    fn join_sources() {
+        let relative = || Source::Path(PathBuf::from("../other.yaml"));
+        assert_eq!(
+            Source::Path(PathBuf::from("/a/b/build.yaml")).join(relative()),
+            Source::Path(PathBuf::from("/a/b/../other.yaml"))
+        );
+        assert_eq!(
+            Source::Url("https://host/a/b/build.yaml".to_string()).join(relative()),
+            Source::Url("https://host/a/other.yaml".to_string())
+        );
+        assert_eq!(
+            Source::parse("git+https://host/repo.git#a/b/build.yaml@v1")
+                .unwrap()
+                .join(relative()),
+            Source::parse("git+https://host/repo.git#a/other.yaml@v1").unwrap()
+        );
+        let absolute = Source::Url("https://other/build.yaml".to_string());
+        assert_eq!(
+            Source::Path(PathBuf::from("build.yaml")).join(absolute.clone()),
+            absolute
+        );
+    }
+
+    #[test]
+    fn cache_key() {
+        assert_eq!(
+            Cache::key("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn url_cache_revalidation() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let cache = Cache::new(dir.path());
+        let (address, server) = serve(vec![
+            ok_response("ETag: \"v1\"\r\n", "build: []"),
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string(),
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        ]);
+        let url = format!("{address}/build.yaml");
+        assert_eq!(cache.fetch_url(&url).unwrap(), "build: []");
+        assert_eq!(cache.fetch_url(&url).unwrap(), "build: []");
+        // Server errors fall back to the cached copy
+        assert_eq!(cache.fetch_url(&url).unwrap(), "build: []");
+        let requests = server.join().unwrap();
+        assert!(!requests[0].to_lowercase().contains("if-none-match"));
+        assert!(requests[1].to_lowercase().contains("if-none-match: \"v1\""));
+        // Network errors fall back to the cached copy
+        assert_eq!(cache.fetch_url(&url).unwrap(), "build: []");
+    }
+
+    #[test]
+    fn url_uncached_failure() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let (address, server) = serve(vec![
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        assert!(Cache::new(dir.path())
+            .fetch_url(&format!("{address}/build.yaml"))
+            .is_err());
+        server.join().unwrap();
+    }
+
+    fn commit(repo: &Repository, contents: &str) -> git2::Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::write(workdir.join("build.yaml"), contents).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("build.yaml")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("yurt", "yurt@example.com").unwrap();
+        let parents = repo
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "commit",
+            &tree,
+            &parents.iter().collect::<Vec<_>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn git_reference() {
+        let remote_dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let cache_dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let remote = Repository::init(remote_dir.path()).unwrap();
+        let first = commit(&remote, "version: first");
+        remote
+            .tag_lightweight("v1", &remote.find_object(first, None).unwrap(), false)
+            .unwrap();
+        commit(&remote, "version: second");
+
+        let cache = Cache::new(cache_dir.path());
+        let url = format!("file://{}", remote_dir.path().display());
+        assert_eq!(
+            cache.fetch_git(&url, "build.yaml", Some("v1")).unwrap(),
+            "version: first"
+        );
+        assert_eq!(
+            cache.fetch_git(&url, "build.yaml", None).unwrap(),
+            "version: second"
+        );
+        // Cached clone picks up new commits
+        commit(&remote, "version: third");
+        assert_eq!(
+            cache.fetch_git(&url, "build.yaml", None).unwrap(),
+            "version: third"
+        );
+        assert!(cache.fetch_git(&url, "missing.yaml", None).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    specs::{permissions::Permissions, BuildUnit, Context, Health, Plan, Resolve},
+    yaml_example_doc,
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Directory that should exist
+#[doc = yaml_example_doc!("directory.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Directory {
+    /// Path of the directory (parents are created as needed)
+    path: PathBuf,
+    /// Permissions of the directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    /// Remove the directory on `yurt uninstall` (only if it is empty)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    remove_on_uninstall: bool,
+}
+
+impl Directory {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return true if the directory exists with the expected permissions
+    pub fn is_created(&self) -> bool {
+        self.path.is_dir()
+            && self
+                .permissions
+                .is_none_or(|mode| mode.mismatch(&self.path).is_none())
+    }
+
+    /// Report whether the directory exists with the expected permissions
+    pub fn health(&self) -> Health {
+        if !self.path.is_dir() {
+            return Health::Unsatisfied("missing".to_string());
+        }
+        match self.permissions.and_then(|mode| mode.mismatch(&self.path)) {
+            Some(mismatch) => Health::Unsatisfied(mismatch),
+            None => Health::Satisfied("exists".to_string()),
+        }
+    }
+
+    /// Create the directory (and its parents) if it does not exist
+    pub fn create(&self) -> Result<()> {
+        if !self.path.is_dir() {
+            log::info!("Creating directory {self}");
+            fs::create_dir_all(&self.path)
+                .with_context(|| format!("Failed to create directory: {self}"))?;
+        }
+        match self.permissions {
+            Some(mode) => mode.apply(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::create`] would make
+    pub fn plan_create(&self) -> Plan {
+        if self.path.exists() && !self.path.is_dir() {
+            return Plan::Fail("Path exists and is not a directory".to_string());
+        }
+        match (self.path.is_dir(), self.permissions) {
+            (false, _) => Plan::Change("create directory".to_string()),
+            (true, Some(mode)) => mode.plan(&self.path),
+            (true, None) => Plan::Satisfied,
+        }
+    }
+
+    /// Remove the directory if [`Self::remove_on_uninstall`] is set and it is empty
+    pub fn remove(&self) -> Result<()> {
+        if !self.remove_on_uninstall || !self.path.is_dir() {
+            return Ok(());
+        }
+        let empty = fs::read_dir(&self.path)
+            .with_context(|| format!("Failed to read directory: {self}"))?
+            .next()
+            .is_none();
+        match empty {
+            true => {
+                log::info!("Removing directory {self}");
+                fs::remove_dir(&self.path)
+                    .with_context(|| format!("Failed to remove directory: {self}"))
+            }
+            false => {
+                log::warn!("Keeping non-empty directory {self}");
+                Ok(())
+            }
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match (self.remove_on_uninstall, self.path.is_dir()) {
+            (false, _) => Plan::Skip,
+            (true, false) => Plan::Satisfied,
+            (true, true) => Plan::Change("remove directory if empty".to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Directory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+impl Resolve for Directory {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::Directory(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(remove_on_uninstall: bool) -> (tempfile::TempDir, Directory) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let directory = Directory {
+            path: dir.path().join("nested").join("dir"),
+            permissions: None,
+            remove_on_uninstall,
+        };
+        (dir, directory)
+    }
+
+    #[test]
+    fn create_and_remove() {
+        let (_dir, directory) = fixture(true);
+        assert_eq!(
+            directory.plan_create(),
+            Plan::Change("create directory".to_string())
+        );
+        assert!(directory.health().is_unsatisfied());
+        directory.create().expect("Failed to create directory");
+        assert!(directory.is_created());
+        assert_eq!(directory.plan_create(), Plan::Satisfied);
+        directory.remove().expect("Failed to remove directory");
+        assert!(!directory.path.exists());
+        assert_eq!(directory.plan_remove(), Plan::Satisfied);
+    }
+
+    #[test]
+    fn remove_keeps_content() {
+        let (_dir, directory) = fixture(true);
+        directory.create().expect("Failed to create directory");
+        fs::write(directory.path.join("file"), "content").unwrap();
+        directory.remove().expect("Failed to remove directory");
+        assert!(directory.path.join("file").is_file());
+    }
+
+    #[test]
+    fn remove_disabled() {
+        let (_dir, directory) = fixture(false);
+        directory.create().expect("Failed to create directory");
+        assert_eq!(directory.plan_remove(), Plan::Skip);
+        directory.remove().expect("Failed to remove directory");
+        assert!(directory.path.is_dir());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn create_with_mode() {
+        let (_dir, directory) = fixture(false);
+        let directory = Directory {
+            permissions: Some(serde_yaml::from_str("\"0700\"").unwrap()),
+            ..directory
+        };
+        directory.create().expect("Failed to create directory");
+        assert!(directory.is_created());
+        assert_eq!(directory.health(), Health::Satisfied("exists".to_string()));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::{
+    digest,
+    specs::{permissions::Permissions, BuildUnit, Context, Health, Plan, Resolve},
+    state::Stamps,
+    yaml_example_doc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+/// Download `url`, failing on unsuccessful responses
+pub fn fetch(url: &str) -> Result<Vec<u8>> {
+    let response = minreq::get(url)
+        .send()
+        .with_context(|| format!("Failed to download: {url}"))?;
+    match response.status_code {
+        200..=299 => Ok(response.into_bytes()),
+        code => bail!(
+            "Download request failed: {code} {} ({url})",
+            response.reason_phrase
+        ),
+    }
+}
+
+/// Fail if the SHA-256 digest of `bytes` is not `expected`
+pub fn verify(bytes: &[u8], expected: &str) -> Result<()> {
+    let actual = digest::sha256(bytes);
+    match actual.eq_ignore_ascii_case(expected) {
+        true => Ok(()),
+        false => bail!("Checksum mismatch: expected {expected}, got {actual}"),
+    }
+}
+
+/// Write `bytes` to `path` by renaming a temporary file into place.
+///
+/// Replacing the file rather than writing into it works while it is being executed.
+pub fn install_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let dir = path
+        .parent()
+        .context("Installed file has no parent directory")?;
+    fs::create_dir_all(dir)?;
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".yurt-tmp");
+    let temp = dir.join(name);
+    fs::write(&temp, bytes)
+        .and_then(|()| fs::rename(&temp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+        .with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// Supported archive formats
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Archive {
+    TarGz,
+    Zip,
+}
+
+impl Archive {
+    /// Infer the archive format from the file name at the end of `url`
+    pub fn from_url(url: &str) -> Result<Self> {
+        let name = url.rsplit('/').next().unwrap_or(url).to_ascii_lowercase();
+        match name.rsplit('.').take(2).collect::<Vec<_>>()[..] {
+            ["gz", "tar"] | ["tgz", _] => Ok(Self::TarGz),
+            ["zip", _] => Ok(Self::Zip),
+            _ => bail!("Unable to infer archive format: {url}"),
+        }
+    }
+
+    /// Read the first file in the archive that matches `member`
+    pub fn extract(self, bytes: &[u8], member: &str) -> Result<Vec<u8>> {
+        let pattern =
+            Pattern::new(member).with_context(|| format!("Invalid member pattern: {member}"))?;
+        let options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        let mut contents = Vec::new();
+        match self {
+            Self::TarGz => {
+                let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bytes));
+                for entry in archive.entries().context("Failed to read tar archive")? {
+                    let mut entry = entry.context("Failed to read tar entry")?;
+                    if entry.header().entry_type().is_file()
+                        && pattern.matches_path_with(&entry.path()?, options)
+                    {
+                        entry.read_to_end(&mut contents)?;
+                        return Ok(contents);
+                    }
+                }
+            }
+            Self::Zip => {
+                let mut archive = zip::ZipArchive::new(Cursor::new(bytes))
+                    .context("Failed to read zip archive")?;
+                for index in 0..archive.len() {
+                    let mut file = archive.by_index(index)?;
+                    if file.is_file()
+                        && file
+                            .enclosed_name()
+                            .is_some_and(|name| pattern.matches_path_with(&name, options))
+                    {
+                        file.read_to_end(&mut contents)?;
+                        return Ok(contents);
+                    }
+                }
+            }
+        }
+        bail!("No archive member matches: {member}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// File matches the checksum
+    Current,
+    /// File does not exist
+    Missing,
+    /// File differs from the download
+    Changed,
+}
+
+/// File downloaded from a url and verified with a SHA-256 checksum
+///
+/// With [`extract`][Self::extract], the download is a `.tar.gz` or `.zip`
+/// archive and the first member matching the glob pattern is installed.
+/// Files are made executable unless [`permissions`][Self::permissions] are set.
+#[doc = yaml_example_doc!("download.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Download {
+    /// Url of the file or archive
+    url: String,
+    /// Hex encoded SHA-256 checksum of the download
+    sha256: String,
+    /// Installed file path
+    path: PathBuf,
+    /// Archive member to install (glob pattern)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extract: Option<String>,
+    /// Permissions of the installed file (`0755` by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    /// Location of the checksums recorded for extracted files
+    #[serde(skip)]
+    stamps: Stamps,
+}
+
+impl Download {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn permissions(&self) -> Option<Permissions> {
+        self.permissions
+            .or(cfg!(unix).then_some(Permissions::EXECUTABLE))
+    }
+
+    /// Keep the checksums of extracted files in `stamps`
+    pub fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// Path of the checksum stamp of an extracted file
+    fn stamp(&self) -> Result<PathBuf> {
+        let key = format!(
+            "{} {} {}",
+            self.url,
+            self.extract.as_deref().unwrap_or_default(),
+            self.path.display()
+        );
+        self.stamps.path("download", &key)
+    }
+
+    /// Stamp recorded for an extracted file with digest `hash`
+    fn stamp_content(&self, hash: &str) -> String {
+        format!("{} {hash}\n", self.sha256)
+    }
+
+    /// Compare the installed file against the checksum
+    fn status(&self) -> Status {
+        let Ok(hash) = digest::sha256_file(&self.path) else {
+            return Status::Missing;
+        };
+        let current = match self.extract {
+            None => hash.eq_ignore_ascii_case(&self.sha256),
+            Some(_) => self
+                .stamp()
+                .and_then(|stamp| Ok(fs::read_to_string(stamp)?))
+                .is_ok_and(|stamp| stamp == self.stamp_content(&hash)),
+        };
+        match current {
+            true => Status::Current,
+            false => Status::Changed,
+        }
+    }
+
+    /// Report whether the file is installed
+    pub fn health(&self) -> Health {
+        match self.status() {
+            Status::Current => match self
+                .permissions()
+                .and_then(|mode| mode.mismatch(&self.path))
+            {
+                Some(mismatch) => Health::Unsatisfied(mismatch),
+                None => Health::Satisfied("installed".to_string()),
+            },
+            Status::Missing => Health::Unsatisfied("missing".to_string()),
+            Status::Changed => Health::Unsatisfied("checksum differs".to_string()),
+        }
+    }
+
+    /// Return true if the file is installed
+    pub fn is_installed(&self) -> bool {
+        self.status() == Status::Current
+    }
+
+    /// Download, verify, and install the file if it is not current
+    pub fn install(&self) -> Result<()> {
+        if self.status() != Status::Current {
+            log::info!("Downloading {self}");
+            let bytes = fetch(&self.url)?;
+            verify(&bytes, &self.sha256).with_context(|| format!("Failed to verify {self}"))?;
+            let bytes = match self.extract {
+                Some(ref member) => Archive::from_url(&self.url)?.extract(&bytes, member)?,
+                None => bytes,
+            };
+            install_file(&self.path, &bytes)?;
+            if self.extract.is_some() {
+                let stamp = self.stamp()?;
+                if let Some(dir) = stamp.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(stamp, self.stamp_content(&digest::sha256(&bytes)))
+                    .context("Failed to write download stamp")?;
+            }
+        }
+        match self.permissions() {
+            Some(mode) => mode.apply(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::install`] would make
+    pub fn plan_install(&self) -> Plan {
+        match self.status() {
+            Status::Current => match self.permissions() {
+                Some(mode) => mode.plan(&self.path),
+                None => Plan::Satisfied,
+            },
+            Status::Missing => Plan::Change(format!("download {}", self.url)),
+            Status::Changed => Plan::Change("replace changed file".to_string()),
+        }
+    }
+
+    /// Remove the file if it is still current
+    pub fn remove(&self) -> Result<()> {
+        match self.status() {
+            Status::Current => {
+                log::info!("Removing {self}");
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("Failed to remove file: {self}"))?;
+                match self.extract {
+                    Some(_) => {
+                        fs::remove_file(self.stamp()?).context("Failed to remove download stamp")
+                    }
+                    None => Ok(()),
+                }
+            }
+            Status::Changed => {
+                log::warn!("Keeping modified file {self}");
+                Ok(())
+            }
+            Status::Missing => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match self.status() {
+            Status::Current => Plan::Change("remove file".to_string()),
+            Status::Changed => Plan::Change("keep modified file".to_string()),
+            Status::Missing => Plan::Satisfied,
+        }
+    }
+}
+
+impl fmt::Display for Download {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.url, self.path.display())
+    }
+}
+
+impl Resolve for Download {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        let url = context.parse_str(&self.url)?;
+        let extract = self
+            .extract
+            .map(|member| context.parse_str(&member))
+            .transpose()?;
+        Ok(BuildUnit::Download(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            sha256: context.parse_str(&self.sha256)?.to_lowercase(),
+            url,
+            extract,
+            stamps: context.stamps.clone(),
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::{ok_response, serve};
+    use std::io::Write;
+
+    const SCRIPT: &str = "#!/bin/sh\necho yurt\n";
+
+    fn fixture(url: &str, sha256: &str) -> (tempfile::TempDir, Download) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let download = Download {
+            url: url.to_string(),
+            sha256: sha256.to_string(),
+            path: dir.path().join("bin").join("tool"),
+            extract: None,
+            permissions: None,
+            stamps: Stamps::new(dir.path().join("stamps")),
+        };
+        (dir, download)
+    }
+
+    #[test]
+    fn archive_format() {
+        assert_eq!(
+            Archive::from_url("https://host/tool-1.0.tar.gz").unwrap(),
+            Archive::TarGz
+        );
+        assert_eq!(
+            Archive::from_url("https://host/tool.ZIP").unwrap(),
+            Archive::Zip
+        );
+        assert!(Archive::from_url("https://host/tool").is_err());
+    }
+
+    #[test]
+    fn extract_tar_gz() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        for (name, content) in [("tool-1.0/README", "readme"), ("tool-1.0/tool", SCRIPT)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        let bytes = builder.into_inner().unwrap().finish().unwrap();
+        let extracted = Archive::TarGz.extract(&bytes, "*/tool").unwrap();
+        assert_eq!(extracted, SCRIPT.as_bytes());
+        assert!(Archive::TarGz.extract(&bytes, "tool").is_err());
+    }
+
+    #[test]
+    fn extract_zip() {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .start_file("bin/tool", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(SCRIPT.as_bytes()).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+        let extracted = Archive::Zip.extract(&bytes, "**/tool").unwrap();
+        assert_eq!(extracted, SCRIPT.as_bytes());
+    }
+
+    #[test]
+    fn install_and_remove() {
+        let (address, server) = serve(vec![ok_response("", SCRIPT)]);
+        let (_dir, download) = fixture(
+            &format!("{address}/tool"),
+            &digest::sha256(SCRIPT.as_bytes()),
+        );
+        assert_eq!(
+            download.health(),
+            Health::Unsatisfied("missing".to_string())
+        );
+        download.install().expect("Failed to install download");
+        server.join().unwrap();
+        assert_eq!(fs::read_to_string(&download.path).unwrap(), SCRIPT);
+        assert!(download.is_installed());
+        assert_eq!(download.plan_install(), Plan::Satisfied);
+        // Rerun is a no-op (the server only answers once)
+        download.install().expect("Failed to rerun install");
+        download.remove().expect("Failed to remove download");
+        assert!(!download.path.exists());
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let (address, server) = serve(vec![ok_response("", SCRIPT)]);
+        let (_dir, download) = fixture(&format!("{address}/tool"), &digest::sha256(b"other"));
+        assert!(download.install().is_err());
+        server.join().unwrap();
+        assert!(!download.path.exists());
+    }
+
+    #[test]
+    fn extracted_stamp() {
+        let (_dir, download) = fixture("https://host/tool.tar.gz", "abc");
+        let download = Download {
+            extract: Some("*/tool".to_string()),
+            ..download
+        };
+        fs::create_dir_all(download.path.parent().unwrap()).unwrap();
+        fs::write(&download.path, SCRIPT).unwrap();
+        assert_eq!(
+            download.plan_install(),
+            Plan::Change("replace changed file".to_string())
+        );
+        let stamp = download.stamp().unwrap();
+        fs::create_dir_all(stamp.parent().unwrap()).unwrap();
+        fs::write(
+            &stamp,
+            download.stamp_content(&digest::sha256(SCRIPT.as_bytes())),
+        )
+        .unwrap();
+        assert!(download.is_installed());
+        // Other members of the same archive have their own stamps
+        let other = Download {
+            extract: Some("*/other".to_string()),
+            ..download.clone()
+        };
+        assert!(!other.is_installed());
+        // Stamps are found for units reloaded from the state file
+        let spec = serde_yaml::to_string(&download).unwrap();
+        let mut reloaded: Download = serde_yaml::from_str(&spec).unwrap();
+        reloaded.set_stamps(download.stamps.clone());
+        assert!(reloaded.is_installed());
+        download.remove().expect("Failed to remove download");
+        assert!(!stamp.exists());
+    }
+
+    #[test]
+    fn install_replaces_file() {
+        let (address, server) = serve(vec![ok_response("", SCRIPT)]);
+        let (_dir, download) = fixture(
+            &format!("{address}/tool"),
+            &digest::sha256(SCRIPT.as_bytes()),
+        );
+        fs::create_dir_all(download.path.parent().unwrap()).unwrap();
+        fs::write(&download.path, "old").unwrap();
+        let old = fs::File::open(&download.path).unwrap();
+        download.install().expect("Failed to install download");
+        server.join().unwrap();
+        // The previous file is replaced rather than rewritten
+        let mut content = String::new();
+        (&old).read_to_string(&mut content).unwrap();
+        assert_eq!(content, "old");
+        assert_eq!(fs::read_to_string(&download.path).unwrap(), SCRIPT);
+    }
+}
@@ -1,22 +1,124 @@
 use crate::{
-    context::{parse::ObjectKey, Context, LocaleSpec},
+    context::{
+        parse::{self, ObjectKey},
+        Context, LocaleSpec, LocaleValue,
+    },
+    matcher,
     specs::{shell::ShellCommand, BuildUnit, ResolveInto},
     yaml_example_doc,
 };
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context as _, Result};
 use indexmap::IndexMap;
+use regex::Regex;
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Expected environment variables
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+enum EnvSpec {
+    /// Variable is set
+    Set(String),
+    /// Variables are set and match their [patterns][LocaleValue]
+    Matches(IndexMap<String, LocaleValue>),
+}
 
+/// Pair of values compared by [`!eq`][Condition::Eq]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EqSpec {
+    left: String,
+    right: String,
+}
+
+/// Value matched against a regular expression by [`!matches`][Condition::Matches]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct MatchesSpec {
+    value: String,
+    regex: String,
+}
+
+/// Version checked against a requirement by [`!version`][Condition::Version]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct VersionSpec {
+    /// Version (`22.04`, `v1.2.3`)
+    value: String,
+    /// Version requirement (`>=22.04, <24.04`)
+    req: String,
+}
+
+/// Return true if `path` is an executable file
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Return true if `command` is an executable path or is found on `PATH`
+fn which(command: &str) -> bool {
+    if command.contains(['/', '\\']) {
+        return is_executable(Path::new(command));
+    }
+    let extensions: Vec<String> = match cfg!(windows) {
+        true => env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE;.CMD;.BAT".to_string())
+            .split(';')
+            .map(String::from)
+            .chain([String::new()])
+            .collect(),
+        false => vec![String::new()],
+    };
+    env::var_os("PATH").is_some_and(|paths| {
+        env::split_paths(&paths).any(|dir| {
+            extensions
+                .iter()
+                .any(|ext| is_executable(&dir.join(format!("{command}{ext}"))))
+        })
+    })
+}
+
+/// Boolean expression evaluated while resolving a [`Case`]
+///
+/// Arguments may only reference variables. Facts are not evaluated, except by
+/// [`!fact`][Condition::Fact].
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "snake_case")]
 enum Condition {
     /// Literal boolean
     Bool(bool),
     /// `true` when [locale][LocaleSpec] matches local environment
-    Locale(LocaleSpec),
+    Locale(Box<LocaleSpec>),
     /// `true` when [shell command][ShellCommand] exits successfully
     Eval(ShellCommand),
+    /// `true` when every named fact matches its [pattern][LocaleValue]
+    Fact(IndexMap<String, LocaleValue>),
+    /// `true` when the path exists
+    Exists(PathBuf),
+    /// `true` when the path is a file
+    IsFile(PathBuf),
+    /// `true` when the path is a directory
+    IsDir(PathBuf),
+    /// `true` when the command is found on `PATH`
+    Which(String),
+    /// `true` when the [environment variables][EnvSpec] are set
+    Env(EnvSpec),
+    /// `true` when both [values][EqSpec] are equal
+    Eq(EqSpec),
+    /// `true` when the [value][MatchesSpec] matches the regular expression
+    Matches(MatchesSpec),
+    /// `true` when the [version][VersionSpec] satisfies the requirement
+    Version(VersionSpec),
     /// `true` when all inner [conditions][Condition] are `true`
     All(Vec<Condition>),
     /// `true` when any inner [conditions][Condition] are `true`
@@ -28,11 +130,63 @@ enum Condition {
 }
 
 impl Condition {
+    /// Resolve the variables and `~` in `path` without evaluating facts
+    fn parse_path(path: &Path, context: &Context) -> Result<PathBuf> {
+        Self::parse_variables(path.to_str().unwrap_or(""), context)
+            .map(|path| context.expand_home(&path).into())
+    }
+
+    /// Resolve the variables in `input` without evaluating facts
+    fn parse_variables(input: &str, context: &Context) -> Result<String> {
+        parse::replace(input, |key| context.variables.try_get(&key))
+    }
+
     fn evaluate(&self, context: &Context) -> Result<bool> {
         match self {
             Self::Bool(literal) => Ok(*literal),
             Self::Locale(spec) => Ok(spec.matches(&context.locale)),
             Self::Eval(command) => command.exec_bool(),
+            Self::Fact(facts) => {
+                for (name, expected) in facts {
+                    if !expected.matches(&context.fact(name)?) {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            Self::Exists(path) => Ok(Self::parse_path(path, context)?.exists()),
+            Self::IsFile(path) => Ok(Self::parse_path(path, context)?.is_file()),
+            Self::IsDir(path) => Ok(Self::parse_path(path, context)?.is_dir()),
+            Self::Which(command) => Ok(which(&Self::parse_variables(command, context)?)),
+            Self::Env(EnvSpec::Set(name)) => {
+                Ok(env::var_os(Self::parse_variables(name, context)?).is_some())
+            }
+            Self::Env(EnvSpec::Matches(vars)) => {
+                for (name, expected) in vars {
+                    match env::var(Self::parse_variables(name, context)?) {
+                        Ok(value) if expected.matches(&value) => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            Self::Eq(EqSpec { left, right }) => {
+                Ok(Self::parse_variables(left, context)? == Self::parse_variables(right, context)?)
+            }
+            Self::Matches(MatchesSpec { value, regex }) => {
+                let regex = Self::parse_variables(regex, context)?;
+                Regex::new(&regex)
+                    .with_context(|| format!("Invalid regex: {regex}"))
+                    .and_then(|regex| Ok(regex.is_match(&Self::parse_variables(value, context)?)))
+            }
+            Self::Version(VersionSpec { value, req }) => {
+                let value = Self::parse_variables(value, context)?;
+                let version = Version::parse(&value)
+                    .ok()
+                    .or_else(|| matcher::version(&value))
+                    .with_context(|| format!("Invalid version: {value}"))?;
+                Ok(matcher::version_req(&Self::parse_variables(req, context)?)?.matches(&version))
+            }
             Self::All(conds) | Self::Any(conds) | Self::Not(conds) => {
                 let evaluated = conds
                     .iter()
@@ -77,6 +231,7 @@ impl<T> ResolveInto for Case<T>
 where
     T: ResolveInto,
 {
+    #[allow(clippy::unnecessary_semicolon)]
     fn resolve_into(self, context: &mut Context, output: &mut Vec<BuildUnit>) -> Result<()> {
         for case in self.0 {
             if case.evaluate(context)? {
@@ -176,6 +331,122 @@ mod tests {
             yaml_condition!("!eval bad-command -a -b", Condition::Eval(_), false);
         }
 
+        #[test]
+        fn fact() {
+            let mut context = Context::default();
+            context.add_fact("wsl".to_string(), "echo false".to_string().into());
+            context.add_fact("host".to_string(), "echo ci-runner-3".to_string().into());
+            let evaluate = |yaml| {
+                serde_yaml::from_str::<Condition>(yaml)
+                    .expect("Deserialization failed")
+                    .evaluate(&context)
+            };
+            assert!(evaluate("!fact { wsl: \"false\", host: ci-* }").unwrap());
+            assert!(!evaluate("!fact { wsl: [\"true\", \"1\"] }").unwrap());
+            assert!(evaluate("!fact { missing: x }").is_err());
+        }
+
+        #[test]
+        fn paths() {
+            let dir = tempfile::tempdir().expect("Failed to create tempdir");
+            let file = dir.path().join("file");
+            std::fs::write(&file, "content").unwrap();
+            let mut context = Context::default();
+            context
+                .variables
+                .try_push("vars.dir", dir.path().to_str().unwrap())
+                .unwrap();
+            let evaluate = |yaml: &str| {
+                serde_yaml::from_str::<Condition>(yaml)
+                    .expect("Deserialization failed")
+                    .evaluate(&context)
+                    .unwrap()
+            };
+            assert!(evaluate("!exists ${{ vars.dir }}/file"));
+            assert!(!evaluate("!exists ${{ vars.dir }}/missing"));
+            assert!(evaluate("!is_file ${{ vars.dir }}/file"));
+            assert!(!evaluate("!is_file ${{ vars.dir }}"));
+            assert!(evaluate("!is_dir ${{ vars.dir }}"));
+            assert!(!evaluate("!is_dir ${{ vars.dir }}/file"));
+        }
+
+        #[test]
+        #[cfg(unix)]
+        fn which() {
+            yaml_condition!("!which sh", Condition::Which(_), true);
+            yaml_condition!("!which /bin/sh", Condition::Which(_), true);
+            yaml_condition!("!which yurt-missing-command", Condition::Which(_), false);
+        }
+
+        #[test]
+        fn env() {
+            // Cargo sets the package variables for test binaries
+            yaml_condition!("!env CARGO_PKG_NAME", Condition::Env(_), true);
+            yaml_condition!("!env YURT_CONDITION_MISSING", Condition::Env(_), false);
+            yaml_condition!("!env { CARGO_PKG_NAME: yu* }", Condition::Env(_), true);
+            yaml_condition!(
+                "!env { CARGO_PKG_NAME: [home, /^personal/] }",
+                Condition::Env(_),
+                false
+            );
+        }
+
+        #[test]
+        fn comparisons() {
+            let mut context = Context::default();
+            context.variables.try_push("vars.profile", "work").unwrap();
+            context.variables.try_push("vars.python", "3.11.4").unwrap();
+            let evaluate = |yaml: &str| {
+                serde_yaml::from_str::<Condition>(yaml)
+                    .expect("Deserialization failed")
+                    .evaluate(&context)
+            };
+            assert!(evaluate("!eq { left: \"${{ vars.profile }}\", right: work }").unwrap());
+            assert!(!evaluate("!eq { left: \"${{ vars.profile }}\", right: home }").unwrap());
+            assert!(evaluate("!matches { value: \"${{ vars.profile }}\", regex: ^w }").unwrap());
+            assert!(!evaluate("!matches { value: \"${{ vars.profile }}\", regex: ^h }").unwrap());
+            assert!(evaluate("!matches { value: x, regex: \"(\" }").is_err());
+            assert!(
+                evaluate("!version { value: \"${{ vars.python }}\", req: \">=3.10, <4\" }")
+                    .unwrap()
+            );
+            assert!(
+                !evaluate("!version { value: \"${{ vars.python }}\", req: \"<3.11\" }").unwrap()
+            );
+            assert!(evaluate("!version { value: rolling, req: \">=1\" }").is_err());
+            // Pre-releases do not satisfy the release
+            assert!(!evaluate("!version { value: 1.2.3-rc1, req: \">=1.2.3\" }").unwrap());
+            assert!(evaluate("!version { value: 1.2.3-rc1, req: \">=1.2.3-rc0\" }").unwrap());
+            // Other versions are parsed leniently
+            assert!(evaluate("!version { value: v22.04, req: \"=22.4\" }").unwrap());
+        }
+
+        #[test]
+        fn arguments_skip_facts() {
+            let dir = tempfile::tempdir().expect("Failed to create tempdir");
+            let marker = dir.path().join("marker");
+            let mut context = Context::default();
+            context.add_fact(
+                "host".to_string(),
+                format!("touch {}", marker.display()).into(),
+            );
+            for yaml in [
+                "!exists \"${{ fact.host }}\"",
+                "!is_file \"${{ fact.host }}\"",
+                "!is_dir \"${{ fact.host }}\"",
+                "!which \"${{ fact.host }}\"",
+                "!env \"${{ fact.host }}\"",
+                "!env { \"${{ fact.host }}\": x }",
+                "!eq { left: \"${{ fact.host }}\", right: x }",
+                "!matches { value: \"${{ fact.host }}\", regex: x }",
+                "!version { value: \"${{ fact.host }}\", req: \">=1\" }",
+            ] {
+                let cond: Condition = serde_yaml::from_str(yaml).expect("Deserialization failed");
+                assert!(cond.evaluate(&context).is_err(), "{yaml}");
+            }
+            assert!(!marker.exists());
+        }
+
         #[test]
         fn bool() {
             yaml_condition!("!bool true", Condition::Bool(true), true);
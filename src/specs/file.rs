@@ -0,0 +1,192 @@
+use crate::{
+    digest,
+    specs::{permissions::Permissions, BuildUnit, Context, Health, Plan, Resolve},
+    yaml_example_doc,
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// File matches the content
+    Current,
+    /// File does not exist
+    Missing,
+    /// File differs from the content
+    Changed,
+}
+
+/// File with inline content
+///
+/// Keys (`${{ }}`) in the content are replaced during resolution.
+#[doc = yaml_example_doc!("file.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct File {
+    /// Path of the file
+    path: PathBuf,
+    /// Content of the file
+    #[serde(default)]
+    content: String,
+    /// Permissions of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+}
+
+impl File {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compare the file against the content
+    fn status(&self) -> Status {
+        match digest::sha256_file(&self.path) {
+            Ok(hash) if hash == digest::sha256(self.content.as_bytes()) => Status::Current,
+            Ok(_) => Status::Changed,
+            Err(_) => Status::Missing,
+        }
+    }
+
+    /// Report whether the file matches the content
+    pub fn health(&self) -> Health {
+        match self.status() {
+            Status::Current => match self.permissions.and_then(|mode| mode.mismatch(&self.path)) {
+                Some(mismatch) => Health::Unsatisfied(mismatch),
+                None => Health::Satisfied("content matches".to_string()),
+            },
+            Status::Missing => Health::Unsatisfied("missing".to_string()),
+            Status::Changed => Health::Unsatisfied("content differs".to_string()),
+        }
+    }
+
+    /// Return true if the file matches the content
+    pub fn is_current(&self) -> bool {
+        self.status() == Status::Current
+    }
+
+    /// Write the file if its content differs
+    pub fn write(&self) -> Result<()> {
+        if self.status() != Status::Current {
+            log::info!("Writing {self}");
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&self.path, &self.content)
+                .with_context(|| format!("Failed to write file: {self}"))?;
+        }
+        match self.permissions {
+            Some(mode) => mode.apply(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::write`] would make
+    pub fn plan_write(&self) -> Plan {
+        match self.status() {
+            Status::Current => match self.permissions {
+                Some(mode) => mode.plan(&self.path),
+                None => Plan::Satisfied,
+            },
+            Status::Missing => Plan::Change("write file".to_string()),
+            Status::Changed => Plan::Change("rewrite changed content".to_string()),
+        }
+    }
+
+    /// Remove the file if it still matches the content
+    pub fn remove(&self) -> Result<()> {
+        match self.status() {
+            Status::Current => {
+                log::info!("Removing {self}");
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("Failed to remove file: {self}"))
+            }
+            Status::Changed => {
+                log::warn!("Keeping modified file {self}");
+                Ok(())
+            }
+            Status::Missing => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match self.status() {
+            Status::Current => Plan::Change("remove file".to_string()),
+            Status::Changed => Plan::Change("keep modified file".to_string()),
+            Status::Missing => Plan::Satisfied,
+        }
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())
+    }
+}
+
+impl Resolve for File {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::File(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            content: context.parse_str(&self.content)?,
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(content: &str) -> (tempfile::TempDir, File) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let file = File {
+            path: dir.path().join("nested").join("file"),
+            content: content.to_string(),
+            permissions: None,
+        };
+        (dir, file)
+    }
+
+    #[test]
+    fn write_and_remove() {
+        let (_dir, file) = fixture("registry=https://example.com\n");
+        assert_eq!(file.plan_write(), Plan::Change("write file".to_string()));
+        file.write().expect("Failed to write file");
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), file.content);
+        assert!(file.is_current());
+        assert_eq!(file.plan_write(), Plan::Satisfied);
+        file.remove().expect("Failed to remove file");
+        assert!(!file.path.exists());
+    }
+
+    #[test]
+    fn remove_keeps_modified() {
+        let (_dir, file) = fixture("content");
+        file.write().expect("Failed to write file");
+        fs::write(&file.path, "edited").unwrap();
+        assert!(file.health().is_unsatisfied());
+        assert_eq!(
+            file.plan_write(),
+            Plan::Change("rewrite changed content".to_string())
+        );
+        file.remove().expect("Failed to remove file");
+        assert_eq!(fs::read_to_string(&file.path).unwrap(), "edited");
+    }
+
+    #[test]
+    fn resolve_content() {
+        let (_dir, file) = fixture("user = ${{ vars.name }}");
+        let mut context = Context::default();
+        context.variables.try_push("vars.name", "yurt").unwrap();
+        match file.resolve(&mut context).unwrap() {
+            BuildUnit::File(file) => assert_eq!(file.content, "user = yurt"),
+            _ => unreachable!(),
+        }
+    }
+}
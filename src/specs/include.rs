@@ -0,0 +1,82 @@
+use crate::{
+    config::Config,
+    source::Source,
+    specs::{BuildUnit, Context, ResolveInto},
+    yaml_example_doc,
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+
+/// Build file whose build steps are resolved in place.
+///
+/// Relative paths are resolved from the location of the including build file.
+/// Remote build files may be included by url (`https://...`) or from a git
+/// repository (`git+https://host/repo.git#path/to/build.yaml@ref`).
+#[doc = yaml_example_doc!("include.yaml")]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(transparent)]
+pub struct Include(String);
+
+impl Include {
+    /// Build file to include, with variables resolved before checking for a remote source
+    fn source(&self, context: &Context) -> Result<Source> {
+        let input = context.parse_str(&self.0)?;
+        let source = match Source::is_remote(&input) {
+            true => Source::parse(&input)?,
+            false => Source::Path(context.expand_home(&input).into()),
+        };
+        Ok(context.relative_source(source))
+    }
+}
+
+impl ResolveInto for Include {
+    fn resolve_into(self, context: &mut Context, output: &mut Vec<BuildUnit>) -> Result<()> {
+        let source = self.source(context)?;
+        Config::from_source(source.clone())
+            .and_then(|config| config.resolve_into(context, output))
+            .with_context(|| format!("Failed to include build file: {source}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn include_relative() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        fs::write(
+            dir.path().join("included.yaml"),
+            "build: [ !package { name: included } ]",
+        )
+        .expect("Failed to write build file");
+        let mut context = Context::default();
+        context
+            .push_file(Source::Path(dir.path().join("build.yaml")))
+            .expect("Failed to push build file");
+        let include = Include("included.yaml".to_string());
+        assert_eq!(include.resolve_into_new(&mut context).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn include_remote_variable() {
+        let mut context = Context::default();
+        context
+            .variables
+            .try_push("vars.base", "https://host/dotfiles")
+            .unwrap();
+        let include = Include("${{ vars.base }}/work.yaml".to_string());
+        assert_eq!(
+            include.source(&context).unwrap(),
+            Source::Url("https://host/dotfiles/work.yaml".to_string())
+        );
+    }
+
+    #[test]
+    fn include_missing() {
+        let include = Include("missing/build.yaml".to_string());
+        assert!(include.resolve_into_new(&mut Context::default()).is_err());
+    }
+}
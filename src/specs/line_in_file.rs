@@ -0,0 +1,386 @@
+use crate::{
+    specs::{BuildUnit, Context, Health, Plan, Resolve},
+    yaml_example_doc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::ErrorKind,
+    iter,
+    path::{Path, PathBuf},
+};
+
+fn default_comment() -> String {
+    "#".to_string()
+}
+
+fn is_default_comment(comment: &str) -> bool {
+    comment == "#"
+}
+
+/// Lines managed inside a file that yurt does not own.
+///
+/// With an [`id`][Self::id], the content is kept in a marked block
+/// (`# BEGIN yurt <id>` ... `# END yurt <id>`) that is updated in place.
+/// Without one, each line of the content is appended if it is missing. Those lines
+/// cannot be told apart from lines that were already in the file, so they are kept
+/// when the unit is uninstalled.
+#[doc = yaml_example_doc!("line_in_file.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct LineInFile {
+    /// Path of the file
+    path: PathBuf,
+    /// Lines to ensure are present
+    content: String,
+    /// Name of the marked block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    /// Comment prefix used for block markers
+    #[serde(
+        default = "default_comment",
+        skip_serializing_if = "is_default_comment"
+    )]
+    comment: String,
+}
+
+impl LineInFile {
+    /// Path of the file and the [`id`][Self::id] (or content) of the line
+    pub fn key(&self) -> (&Path, &str) {
+        (&self.path, self.id.as_deref().unwrap_or(&self.content))
+    }
+
+    fn begin_marker(&self, id: &str) -> String {
+        format!("{} BEGIN yurt {id}", self.comment)
+    }
+
+    fn end_marker(&self, id: &str) -> String {
+        format!("{} END yurt {id}", self.comment)
+    }
+
+    /// Read the current file content (empty if the file does not exist)
+    fn read(&self) -> Result<String> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read file: {self}")),
+        }
+    }
+
+    /// Index range of the marked block in `lines` (including markers)
+    fn find_block(&self, lines: &[&str], id: &str) -> Result<Option<(usize, usize)>> {
+        let begin = self.begin_marker(id);
+        let end = self.end_marker(id);
+        let Some(start) = lines.iter().position(|line| line.trim_end() == begin) else {
+            return Ok(None);
+        };
+        match lines[start..]
+            .iter()
+            .position(|line| line.trim_end() == end)
+        {
+            Some(stop) => Ok(Some((start, start + stop))),
+            None => bail!("Block is missing its end marker ({end}): {self}"),
+        }
+    }
+
+    /// File content with the lines present.
+    ///
+    /// Other lines, the line endings, and whether the file ends with a newline are kept.
+    fn insert(&self, existing: &str) -> Result<String> {
+        let eol = line_ending(existing);
+        let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+        let added: Vec<String> = match self.id {
+            Some(ref id) => {
+                let block: Vec<String> = iter::once(self.begin_marker(id))
+                    .chain(self.content.lines().map(String::from))
+                    .chain(iter::once(self.end_marker(id)))
+                    .collect();
+                if let Some((start, stop)) = self.find_block(&lines, id)? {
+                    let terminated = lines[stop].ends_with('\n');
+                    return Ok(lines[..start].concat()
+                        + &join_lines(&block, eol, terminated)
+                        + &lines[stop + 1..].concat());
+                }
+                block
+            }
+            None => self
+                .content
+                .lines()
+                .filter(|line| !existing.lines().any(|existing| existing == *line))
+                .map(String::from)
+                .collect(),
+        };
+        if added.is_empty() {
+            return Ok(existing.to_string());
+        }
+        let terminated = existing.is_empty() || existing.ends_with('\n');
+        let separator = if terminated { "" } else { eol };
+        Ok(existing.to_string() + separator + &join_lines(&added, eol, terminated))
+    }
+
+    /// File content with the marked block removed
+    fn strip(&self, existing: &str, id: &str) -> Result<String> {
+        let lines: Vec<&str> = existing.split_inclusive('\n').collect();
+        let Some((start, stop)) = self.find_block(&lines, id)? else {
+            return Ok(existing.to_string());
+        };
+        let before = lines[..start].concat();
+        let after = lines[stop + 1..].concat();
+        // A block at the end of a file without a final newline took the newline of the line before
+        if after.is_empty() && !lines[stop].ends_with('\n') {
+            let before = before.strip_suffix('\n').unwrap_or(&before);
+            return Ok(before.strip_suffix('\r').unwrap_or(before).to_string());
+        }
+        Ok(before + &after)
+    }
+
+    /// Return true if any of the lines are present
+    fn is_partial(&self, existing: &str) -> Result<bool> {
+        match self.id {
+            Some(ref id) => Ok(self.strip(existing, id)? != existing),
+            None => Ok(existing
+                .lines()
+                .any(|line| self.content.lines().any(|content| content == line))),
+        }
+    }
+
+    /// Return true if the lines are present and up to date
+    pub fn is_present(&self) -> bool {
+        self.read()
+            .and_then(|existing| Ok(self.insert(&existing)? == existing))
+            .unwrap_or(false)
+    }
+
+    /// Report whether the lines are present and up to date
+    pub fn health(&self) -> Health {
+        let status = self.read().and_then(|existing| {
+            Ok((
+                self.insert(&existing)? == existing,
+                self.is_partial(&existing)?,
+            ))
+        });
+        match status {
+            Ok((true, _)) => Health::Satisfied("present".to_string()),
+            Ok((false, true)) => Health::Unsatisfied("content differs".to_string()),
+            Ok((false, false)) => Health::Unsatisfied("missing".to_string()),
+            Err(e) => Health::Unsatisfied(format!("{e:#}")),
+        }
+    }
+
+    /// Insert or update the lines
+    pub fn write(&self) -> Result<()> {
+        let existing = self.read()?;
+        let updated = self.insert(&existing)?;
+        if updated == existing {
+            return Ok(());
+        }
+        log::info!("Updating lines in {self}");
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, updated).with_context(|| format!("Failed to write file: {self}"))
+    }
+
+    /// Describe the changes that [`Self::write`] would make
+    pub fn plan_write(&self) -> Plan {
+        let status = self.read().and_then(|existing| {
+            Ok((
+                self.insert(&existing)? == existing,
+                self.is_partial(&existing)?,
+            ))
+        });
+        match status {
+            Ok((true, _)) => Plan::Satisfied,
+            Ok((false, true)) => Plan::Change("update lines".to_string()),
+            Ok((false, false)) => Plan::Change("insert lines".to_string()),
+            Err(e) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+
+    /// Remove the marked block (the file itself is kept)
+    pub fn remove(&self) -> Result<()> {
+        let Some(ref id) = self.id else {
+            log::warn!("Keeping lines without an id in {self}");
+            return Ok(());
+        };
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let existing = self.read()?;
+        let updated = self.strip(&existing, id)?;
+        if updated == existing {
+            return Ok(());
+        }
+        log::info!("Removing lines from {self}");
+        fs::write(&self.path, updated).with_context(|| format!("Failed to write file: {self}"))
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        let Some(ref id) = self.id else {
+            return Plan::Skip;
+        };
+        match self
+            .read()
+            .and_then(|existing| Ok(self.strip(&existing, id)? == existing))
+        {
+            Ok(true) => Plan::Satisfied,
+            Ok(false) => Plan::Change("remove lines".to_string()),
+            Err(e) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+}
+
+/// Line ending used by `text` (`\n` unless the first line ends with `\r\n`)
+fn line_ending(text: &str) -> &'static str {
+    match text.lines().next() {
+        Some(line) if text[line.len()..].starts_with("\r\n") => "\r\n",
+        _ => "\n",
+    }
+}
+
+/// Join `lines` with `eol` (ending with `eol` if `terminated`)
+fn join_lines(lines: &[String], eol: &str, terminated: bool) -> String {
+    let text = lines.join(eol);
+    match terminated {
+        true => text + eol,
+        false => text,
+    }
+}
+
+impl fmt::Display for LineInFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.id {
+            Some(ref id) => write!(f, "{} ({id})", self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+impl Resolve for LineInFile {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::LineInFile(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            content: context.parse_str(&self.content)?,
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(existing: &str, id: Option<&str>, content: &str) -> (tempfile::TempDir, LineInFile) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let path = dir.path().join(".bashrc");
+        fs::write(&path, existing).expect("Failed to write file");
+        let unit = LineInFile {
+            path,
+            content: content.to_string(),
+            id: id.map(String::from),
+            comment: default_comment(),
+        };
+        (dir, unit)
+    }
+
+    #[test]
+    fn block_insert_update_remove() {
+        let (_dir, unit) = fixture("export A=1\n", Some("path"), "export PATH=~/bin:$PATH");
+        assert_eq!(unit.plan_write(), Plan::Change("insert lines".to_string()));
+        unit.write().expect("Failed to write block");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "export A=1\n# BEGIN yurt path\nexport PATH=~/bin:$PATH\n# END yurt path\n"
+        );
+        assert!(unit.is_present());
+        // Update in place
+        let updated = LineInFile {
+            content: "export PATH=~/.local/bin:$PATH".to_string(),
+            ..unit.clone()
+        };
+        assert_eq!(
+            updated.plan_write(),
+            Plan::Change("update lines".to_string())
+        );
+        fs::write(
+            &unit.path,
+            fs::read_to_string(&unit.path).unwrap() + "export B=2\n",
+        )
+        .unwrap();
+        updated.write().expect("Failed to update block");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "export A=1\n# BEGIN yurt path\nexport PATH=~/.local/bin:$PATH\n# END yurt path\nexport B=2\n"
+        );
+        updated.remove().expect("Failed to remove block");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "export A=1\nexport B=2\n"
+        );
+        assert_eq!(updated.plan_remove(), Plan::Satisfied);
+    }
+
+    #[test]
+    fn line_insert_remove() {
+        let (_dir, unit) = fixture("127.0.0.1 localhost", None, "10.0.0.2 nas");
+        unit.write().expect("Failed to write line");
+        // The file still has no final newline
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.2 nas"
+        );
+        assert!(unit.is_present());
+        assert_eq!(unit.plan_write(), Plan::Satisfied);
+        // Lines without an id may have been in the file before
+        assert_eq!(unit.plan_remove(), Plan::Skip);
+        unit.remove().expect("Failed to remove line");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "127.0.0.1 localhost\n10.0.0.2 nas"
+        );
+    }
+
+    #[test]
+    fn block_keeps_line_endings() {
+        let (_dir, unit) = fixture("export A=1\r\nexport B=2", Some("path"), "export C=3");
+        unit.write().expect("Failed to write block");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "export A=1\r\nexport B=2\r\n# BEGIN yurt path\r\nexport C=3\r\n# END yurt path"
+        );
+        assert!(unit.is_present());
+        unit.remove().expect("Failed to remove block");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "export A=1\r\nexport B=2"
+        );
+    }
+
+    #[test]
+    fn unterminated_block() {
+        let (_dir, unit) = fixture("# BEGIN yurt path\nexport A=1\n", Some("path"), "line");
+        assert!(unit.write().is_err());
+        assert!(unit.remove().is_err());
+        assert!(matches!(unit.plan_write(), Plan::Fail(_)));
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "# BEGIN yurt path\nexport A=1\n"
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let (dir, unit) = fixture("", Some("id"), "line");
+        let unit = LineInFile {
+            path: dir.path().join("missing"),
+            ..unit
+        };
+        assert!(unit.health().is_unsatisfied());
+        assert_eq!(unit.plan_remove(), Plan::Satisfied);
+        unit.remove().expect("Failed to remove block");
+        unit.write().expect("Failed to write block");
+        assert!(unit.is_present());
+    }
+}
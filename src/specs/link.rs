@@ -1,19 +1,72 @@
-use crate::specs::{BuildUnit, Context, Resolve};
+use crate::specs::{
+    permissions::Permissions, BuildUnit, Context, Health, Plan, Resolve, ResolveInto,
+};
 use crate::yaml_example_doc;
+use crate::{backup, digest, state::Stamps};
 
-use anyhow::{anyhow, Context as _, Error, Result};
+use anyhow::{anyhow, bail, Context as _, Error, Result};
+use glob::{MatchOptions, Pattern};
 use serde::{Deserialize, Serialize};
-use std::{fmt, fs, path::PathBuf};
+use std::{
+    fmt, fs,
+    io::ErrorKind,
+    iter,
+    path::{Component, Path, PathBuf},
+};
 
 #[derive(Debug)]
 enum Status {
     Valid,
     NullSource,
     NullTarget,
+    /// Copy is unmodified but the target changed since it was copied
+    Outdated,
     InvalidSource(Error),
     InvalidTarget(Error),
 }
 
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Valid => write!(f, "Valid"),
+            Self::NullSource => write!(f, "NullSource: link does not exist"),
+            Self::NullTarget => write!(f, "NullTarget: link target does not exist"),
+            Self::Outdated => write!(f, "Outdated: link target changed since it was copied"),
+            Self::InvalidSource(e) => write!(f, "InvalidSource: {e}"),
+            Self::InvalidTarget(e) => write!(f, "InvalidTarget: {e}"),
+        }
+    }
+}
+
+/// Method used to place the target at the link source
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkMode {
+    /// Symbolic link to the target
+    #[default]
+    Symlink,
+    /// Copy of the target file or directory tree
+    Copy,
+    /// Hard link to the target file
+    Hardlink,
+}
+
+impl LinkMode {
+    fn is_symlink(&self) -> bool {
+        *self == Self::Symlink
+    }
+}
+
+impl fmt::Display for LinkMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Symlink => write!(f, "symlink"),
+            Self::Copy => write!(f, "copy"),
+            Self::Hardlink => write!(f, "hardlink"),
+        }
+    }
+}
+
 /// Symbolic link representation ([`source`][Self::source] -> [`target`][Self::target])
 #[doc = yaml_example_doc!("link.yaml")]
 #[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
@@ -22,10 +75,22 @@ pub struct Link {
     source: PathBuf,
     /// Path of the symbolic link
     target: PathBuf,
+    /// How the link is applied (defaults to [`LinkMode::Symlink`])
+    #[serde(default, skip_serializing_if = "LinkMode::is_symlink")]
+    mode: LinkMode,
+    /// Point symlinks at a path relative to the link location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    relative: Option<bool>,
+    /// Permissions of the linked file (`mode` selects the link method)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    /// Location of the hashes recorded for copies
+    #[serde(skip)]
+    stamps: Stamps,
 }
 
 impl Link {
-    fn new<S, T>(source: S, target: T) -> Self
+    pub fn new<S, T>(source: S, target: T) -> Self
     where
         S: Into<PathBuf>,
         T: Into<PathBuf>,
@@ -33,16 +98,35 @@ impl Link {
         Self {
             source: source.into(),
             target: target.into(),
+            mode: LinkMode::default(),
+            relative: None,
+            permissions: None,
+            stamps: Stamps::default(),
         }
     }
 
+    /// Path of the link
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
     /// Get current status of link
     fn status(&self) -> Status {
         if !self.target.exists() {
             return Status::NullTarget;
         }
+        match self.mode {
+            LinkMode::Symlink => self.symlink_status(),
+            LinkMode::Copy | LinkMode::Hardlink => self.file_status(),
+        }
+    }
+
+    /// Get current status of a symbolic link
+    fn symlink_status(&self) -> Status {
         match self.source.read_link() {
-            Ok(target) if target == self.target => Status::Valid,
+            Ok(target) if target == self.target || self.resolves_to_target(&target) => {
+                Status::Valid
+            }
             Ok(target) => Status::InvalidTarget(anyhow!(
                 "Link source points to wrong target: {}",
                 Self::new(self.source.clone(), target)
@@ -52,11 +136,125 @@ impl Link {
         }
     }
 
+    /// Return true if the symlink contents `link` lead to the target
+    fn resolves_to_target(&self, link: &Path) -> bool {
+        let link = match self.source.parent() {
+            Some(dir) => dir.join(link),
+            None => link.to_path_buf(),
+        };
+        match (fs::canonicalize(link), fs::canonicalize(&self.target)) {
+            (Ok(link), Ok(target)) => link == target,
+            _ => false,
+        }
+    }
+
+    /// Create a symlink at source (relative to the source directory if configured)
+    fn symlink(&self) -> Result<()> {
+        let target = match self.relative {
+            Some(true) => {
+                let dir = self
+                    .source
+                    .parent()
+                    .context("Link source has no parent directory")?;
+                relative_path(&fs::canonicalize(dir)?, &fs::canonicalize(&self.target)?)
+            }
+            _ => self.target.clone(),
+        };
+        match self.target.is_dir() {
+            true => symlink::symlink_dir(target, &self.source),
+            false => symlink::symlink_file(target, &self.source),
+        }
+        .map_err(Error::from)
+    }
+
+    /// Get current status of a copy or hard link
+    fn file_status(&self) -> Status {
+        if self.source.symlink_metadata().is_err() {
+            return Status::NullSource;
+        }
+        if self.source.read_link().is_ok() {
+            return Status::InvalidSource(anyhow!("Link source is a symlink"));
+        }
+        let valid = match self.mode {
+            LinkMode::Hardlink => same_file(&self.source, &self.target),
+            _ => match (
+                digest::sha256_path(&self.source),
+                digest::sha256_path(&self.target),
+            ) {
+                (Ok(source), Ok(target)) if source == target => true,
+                (Ok(source), Ok(_)) if self.copied_hash().is_some_and(|hash| hash == source) => {
+                    return Status::Outdated;
+                }
+                (Ok(_), Ok(_)) => false,
+                (Err(e), _) | (_, Err(e)) => return Status::InvalidSource(e),
+            },
+        };
+        match (valid, &self.mode) {
+            (true, _) => Status::Valid,
+            (false, LinkMode::Hardlink) => {
+                Status::InvalidSource(anyhow!("Link source is not a hard link to the target"))
+            }
+            (false, _) => Status::InvalidSource(anyhow!("Link source content differs from target")),
+        }
+    }
+
+    /// Keep the hashes of copies in `stamps`
+    pub fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// Path of the stamp that records the hash of the copy
+    fn stamp(&self) -> Result<PathBuf> {
+        self.stamps.path("link", &self.source.to_string_lossy())
+    }
+
+    /// Hash of the copy when it was written
+    fn copied_hash(&self) -> Option<String> {
+        self.stamp()
+            .ok()
+            .and_then(|stamp| fs::read_to_string(stamp).ok())
+    }
+
+    /// Copy the target to source and record the hash of the copy
+    fn copy(&self) -> Result<()> {
+        copy_path(&self.target, &self.source)?;
+        let stamp = self.stamp()?;
+        if let Some(dir) = stamp.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(stamp, digest::sha256_path(&self.source)?).context("Failed to write copy stamp")
+    }
+
+    /// Remove the link, copy, or hard link at source
+    fn remove(&self) -> Result<()> {
+        match self.mode {
+            LinkMode::Symlink if self.target.is_file() => {
+                symlink::remove_symlink_file(&self.source)
+            }
+            LinkMode::Symlink => symlink::remove_symlink_dir(&self.source),
+            LinkMode::Copy if self.source.is_dir() => fs::remove_dir_all(&self.source),
+            LinkMode::Copy | LinkMode::Hardlink => fs::remove_file(&self.source),
+        }
+        .with_context(|| format!("Failed to remove {}: {self}", self.mode))
+    }
+
     /// Return true if the link is valid
     pub fn is_valid(&self) -> bool {
         matches!(self.status(), Status::Valid)
     }
 
+    /// Report the current status of the link
+    pub fn health(&self) -> Health {
+        match self.status() {
+            status @ Status::Valid => match self.permissions.and_then(|p| p.mismatch(&self.source))
+            {
+                Some(mismatch) => Health::Unsatisfied(mismatch),
+                None => Health::Satisfied(status.to_string()),
+            },
+            status => Health::Unsatisfied(status.to_string()),
+        }
+    }
+
     /// Try to create link if it does not already exist
     pub fn link(&self, clean: bool) -> Result<()> {
         if clean {
@@ -69,56 +267,327 @@ impl Link {
                 if let Some(dir) = self.source.parent() {
                     fs::create_dir_all(dir)?;
                 }
-                symlink::symlink_auto(&self.target, &self.source)
-                    .with_context(|| format!("Failed to apply symlink: {self}"))
+                match self.mode {
+                    LinkMode::Symlink => self.symlink(),
+                    LinkMode::Copy => self.copy(),
+                    LinkMode::Hardlink => {
+                        fs::hard_link(&self.target, &self.source).map_err(Error::from)
+                    }
+                }
+                .with_context(|| format!("Failed to apply {}: {self}", self.mode))
+            }
+            Status::Outdated => {
+                log::info!("Updating {self}");
+                self.remove()?;
+                self.copy()
+                    .with_context(|| format!("Failed to update {}: {self}", self.mode))
             }
             Status::NullTarget => Err(anyhow!("Link target does not exist")),
             Status::InvalidSource(e) => Err(e.context("Invalid link source")),
             Status::InvalidTarget(e) => Err(e.context("Invalid link target")),
+        }?;
+        match self.permissions {
+            Some(permissions) => permissions.apply(&self.source),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::link`] would make
+    pub fn plan_link(&self, clean: bool) -> Plan {
+        match self.status() {
+            Status::Valid => match self.permissions {
+                Some(permissions) => permissions.plan(&self.source),
+                None => Plan::Satisfied,
+            },
+            Status::NullSource => Plan::Change(format!("create {}", self.mode)),
+            Status::Outdated => Plan::Change(format!("update {}", self.mode)),
+            Status::NullTarget => Plan::Fail("Link target does not exist".to_string()),
+            Status::InvalidSource(_) | Status::InvalidTarget(_) if clean => Plan::Change(format!(
+                "back up conflicting source and create {}",
+                self.mode
+            )),
+            Status::InvalidSource(e) => Plan::Fail(format!("Invalid link source: {e}")),
+            Status::InvalidTarget(e) => Plan::Fail(format!("Invalid link target: {e}")),
+        }
+    }
+
+    /// Describe the changes that [`Self::unlink`] would make
+    pub fn plan_unlink(&self) -> Plan {
+        match self.status() {
+            Status::Valid | Status::Outdated => Plan::Change(format!("remove {}", self.mode)),
+            _ => Plan::Satisfied,
         }
     }
 
     /// Try to remove link if it exists
     pub fn unlink(&self) -> Result<()> {
         match self.status() {
-            Status::Valid => {
+            Status::Valid | Status::Outdated => {
                 log::info!("Unlinking {self}");
-                if self.target.is_file() {
-                    symlink::remove_symlink_file(&self.source)
-                } else {
-                    symlink::remove_symlink_dir(&self.source)
+                self.remove()?;
+                match self.mode {
+                    LinkMode::Copy => match fs::remove_file(self.stamp()?) {
+                        Err(e) if e.kind() != ErrorKind::NotFound => {
+                            Err(e).context("Failed to remove copy stamp")
+                        }
+                        _ => Ok(()),
+                    },
+                    _ => Ok(()),
                 }
-                .with_context(|| format!("Failed to remove symlink: {self}"))
             }
             _ => Ok(()),
         }
     }
 
-    /// Remove any conflicting files/links at source
+    /// Reason that the file at source cannot be adopted (if any)
+    fn adopt_conflict(&self) -> Option<String> {
+        if self.source.read_link().is_ok() || !self.source.exists() {
+            Some(format!(
+                "Adopted path must be an existing file or directory: {}",
+                self.source.display()
+            ))
+        } else if self.target.symlink_metadata().is_ok() {
+            Some(format!(
+                "Adoption target already exists: {}",
+                self.target.display()
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Move the file or directory at source to target and link it back
+    pub fn adopt(&self) -> Result<()> {
+        if let Some(conflict) = self.adopt_conflict() {
+            bail!(conflict);
+        }
+        log::info!("Adopting {self}");
+        if let Some(dir) = self.target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        move_path(&self.source, &self.target)
+            .with_context(|| format!("Failed to move adopted path: {self}"))?;
+        self.link(false)
+    }
+
+    /// Describe the changes that [`Self::adopt`] would make
+    pub fn plan_adopt(&self) -> Plan {
+        match self.adopt_conflict() {
+            Some(conflict) => Plan::Fail(conflict),
+            None => Plan::Change(format!(
+                "move {} to {} and create {}",
+                self.source.display(),
+                self.target.display(),
+                self.mode
+            )),
+        }
+    }
+
+    /// Move any conflicting files/directories/links at source to a backup
     pub fn clean(&self) -> Result<()> {
         match self.status() {
             Status::InvalidSource(_) | Status::InvalidTarget(_) => {
-                log::info!("Removing {:?}", &self.source);
-                fs::remove_file(&self.source)
-                    .with_context(|| format!("Failed to clean link source: {self}"))
+                let backup = backup::create(&self.source)
+                    .with_context(|| format!("Failed to clean link source: {self}"))?;
+                log::info!("Moved {} to {}", self.source.display(), backup.display());
+                Ok(())
             }
             _ => Ok(()),
         }
     }
+
+    /// Remove the link and move the most recent backup of the source back into place
+    pub fn restore(&self) -> Result<()> {
+        if backup::latest(&self.source)?.is_none() {
+            return Ok(());
+        }
+        self.unlink()?;
+        if self.source.symlink_metadata().is_ok() {
+            bail!("Link source is occupied by unmanaged files: {self}");
+        }
+        backup::restore(&self.source).map(|_| ())
+    }
+
+    /// Describe the changes that [`Self::restore`] would make
+    pub fn plan_restore(&self) -> Plan {
+        match backup::latest(&self.source) {
+            Ok(Some(backup)) => {
+                if self.is_valid() || self.source.symlink_metadata().is_err() {
+                    Plan::Change(format!("restore {}", backup.display()))
+                } else {
+                    Plan::Fail("Link source is occupied by unmanaged files".to_string())
+                }
+            }
+            Ok(None) => Plan::Satisfied,
+            Err(e) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+}
+
+/// Links for each file below a directory ([`source`][Self::source] -> [`target`][Self::target])
+///
+/// Files in the target directory are expanded into individual [`Link`] units
+/// during resolution, so the target directory must exist when the build is resolved.
+/// Patterns are matched against paths relative to the target.
+#[doc = yaml_example_doc!("link_tree.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct LinkTree {
+    /// Directory that will contain the links
+    source: PathBuf,
+    /// Directory of real source files
+    target: PathBuf,
+    /// Glob patterns of files to link (defaults to every file)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    include: Vec<String>,
+    /// Glob patterns of files to skip
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude: Vec<String>,
+    /// How each link is applied (defaults to [`LinkMode::Symlink`])
+    #[serde(default, skip_serializing_if = "LinkMode::is_symlink")]
+    mode: LinkMode,
+    /// Point each symlink at a path relative to the link location
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    relative: Option<bool>,
+    /// Permissions of each linked file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+}
+
+impl LinkTree {
+    const MATCH_OPTIONS: MatchOptions = MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+
+    fn patterns(context: &Context, globs: &[String]) -> Result<Vec<Pattern>> {
+        globs
+            .iter()
+            .map(|glob| {
+                Pattern::new(&context.parse_str(glob)?)
+                    .with_context(|| format!("Invalid glob pattern: {glob}"))
+            })
+            .collect()
+    }
+}
+
+impl ResolveInto for LinkTree {
+    fn resolve_into(self, context: &mut Context, output: &mut Vec<BuildUnit>) -> Result<()> {
+        let source = PathBuf::from(context.parse_path(self.source.to_str().unwrap_or(""))?);
+        let target = PathBuf::from(context.parse_path(self.target.to_str().unwrap_or(""))?);
+        let include = Self::patterns(context, &self.include)?;
+        let exclude = Self::patterns(context, &self.exclude)?;
+        if !target.is_dir() {
+            bail!("Link tree target is not a directory: {}", target.display());
+        }
+        let mut files = Vec::new();
+        digest::walk(&target, &mut files)?;
+        files.sort();
+        for file in files {
+            let relative = file.strip_prefix(&target)?;
+            let matches =
+                |pattern: &Pattern| pattern.matches_path_with(relative, Self::MATCH_OPTIONS);
+            if (include.is_empty() || include.iter().any(matches)) && !exclude.iter().any(matches) {
+                output.push(BuildUnit::Link(Link {
+                    source: source.join(relative),
+                    target: file.clone(),
+                    mode: self.mode.clone(),
+                    relative: self.relative.or(context.relative_links.then_some(true)),
+                    permissions: self.permissions,
+                    stamps: context.stamps.clone(),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path of `to` relative to the directory `from` (both must be absolute)
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    iter::repeat_n(Component::ParentDir, from.len() - common)
+        .chain(to[common..].iter().copied())
+        .collect()
+}
+
+/// Recursively copy the file or directory at `from` to `to`.
+///
+/// Symbolic links below `from` are recreated rather than followed.
+fn copy_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let (from, to) = (entry.path(), to.join(entry.file_name()));
+            match from.read_link() {
+                Ok(link) if from.is_dir() => symlink::symlink_dir(link, to)?,
+                Ok(link) => symlink::symlink_file(link, to)?,
+                Err(_) => copy_path(&from, &to)?,
+            }
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Move the file or directory at `from` to `to` (copying across filesystems)
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+/// Return true if `a` and `b` are hard links to the same file
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Return true if `a` and `b` have the same content
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (digest::sha256_file(a), digest::sha256_file(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
 }
 
 impl fmt::Display for Link {
+    #[allow(clippy::unnecessary_debug_formatting)]
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?} -> {:?}", &self.source, &self.target)
+        write!(f, "{:?} -> {:?}", &self.source, &self.target)?;
+        match &self.mode {
+            LinkMode::Symlink => Ok(()),
+            mode => write!(f, " ({mode})"),
+        }
     }
 }
 
 impl Resolve for Link {
     fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
-        Ok(BuildUnit::Link(Self::new(
-            context.parse_path(self.source.to_str().unwrap_or(""))?,
-            context.parse_path(self.target.to_str().unwrap_or(""))?,
-        )))
+        Ok(BuildUnit::Link(Self {
+            mode: self.mode,
+            relative: self.relative.or(context.relative_links.then_some(true)),
+            permissions: self.permissions,
+            stamps: context.stamps.clone(),
+            ..Self::new(
+                context.parse_path(self.source.to_str().unwrap_or(""))?,
+                context.parse_path(self.target.to_str().unwrap_or(""))?,
+            )
+        }))
     }
 }
 
@@ -180,6 +649,18 @@ mod tests {
         assert!(!link.is_valid());
     }
 
+    #[test]
+    fn health() {
+        let (_dir, link) = fixture();
+        File::create(&link.target).expect("Failed to create tempfile");
+        assert_eq!(
+            link.health(),
+            Health::Unsatisfied("NullSource: link does not exist".to_string())
+        );
+        link.link(false).expect("Failed to create link");
+        assert_eq!(link.health(), Health::Satisfied("Valid".to_string()));
+    }
+
     #[test]
     fn link_normal() {
         let (_dir, link) = fixture();
@@ -218,6 +699,325 @@ mod tests {
         assert!(!link.source.exists());
     }
 
+    #[test]
+    fn plan_link() {
+        let (_dir, link) = fixture();
+        assert!(matches!(link.plan_link(false), Plan::Fail(_)));
+        File::create(&link.target).expect("Failed to create tempfile");
+        assert!(matches!(link.plan_link(false), Plan::Change(_)));
+        link.link(false).expect("Failed to create link");
+        assert_eq!(link.plan_link(false), Plan::Satisfied);
+    }
+
+    #[test]
+    fn plan_link_clean() {
+        let (_dir, link) = fixture();
+        File::create(&link.target).expect("Failed to create tempfile");
+        File::create(&link.source).expect("Failed to create tempfile");
+        assert!(matches!(link.plan_link(false), Plan::Fail(_)));
+        assert!(matches!(link.plan_link(true), Plan::Change(_)));
+        // Planning must not modify the filesystem
+        assert!(link.source.is_file());
+    }
+
+    #[test]
+    fn plan_unlink() {
+        let (_dir, link) = fixture();
+        File::create(&link.target).expect("Failed to create tempfile");
+        assert_eq!(link.plan_unlink(), Plan::Satisfied);
+        link.link(false).expect("Failed to create link");
+        assert!(matches!(link.plan_unlink(), Plan::Change(_)));
+        assert!(link.is_valid());
+    }
+
+    fn fixture_mode(mode: LinkMode) -> (tempfile::TempDir, Link) {
+        let (dir, link) = fixture();
+        let stamps = Stamps::new(dir.path().join("stamps"));
+        (
+            dir,
+            Link {
+                mode,
+                stamps,
+                ..link
+            },
+        )
+    }
+
+    #[test]
+    fn copy_file() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        fs::write(&link.target, "content").expect("Failed to write target");
+        assert_eq!(
+            link.plan_link(false),
+            Plan::Change("create copy".to_string())
+        );
+        link.link(false).expect("Failed to copy target");
+        assert!(link.source.read_link().is_err());
+        assert_eq!(fs::read_to_string(&link.source).unwrap(), "content");
+        assert!(link.is_valid());
+        let stamp = link.stamp().unwrap();
+        assert!(stamp.exists());
+        link.unlink().expect("Failed to remove copy");
+        assert!(!link.source.exists());
+        assert!(!stamp.exists());
+    }
+
+    #[test]
+    fn copy_tree() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        fs::create_dir_all(link.target.join("nested")).expect("Failed to create tempdir");
+        fs::write(link.target.join("nested").join("file"), "content").unwrap();
+        link.link(false).expect("Failed to copy target");
+        assert!(link.source.join("nested").join("file").is_file());
+        assert!(link.is_valid());
+        link.unlink().expect("Failed to remove copy");
+        assert!(!link.source.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_tree_symlinks() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        fs::create_dir_all(link.target.join("nested")).expect("Failed to create tempdir");
+        fs::write(link.target.join("file"), "content").unwrap();
+        std::os::unix::fs::symlink("../file", link.target.join("nested").join("file")).unwrap();
+        std::os::unix::fs::symlink("..", link.target.join("nested").join("parent")).unwrap();
+        link.link(false).expect("Failed to copy target");
+        assert_eq!(
+            link.source
+                .join("nested")
+                .join("parent")
+                .read_link()
+                .unwrap(),
+            PathBuf::from("..")
+        );
+        assert_eq!(
+            fs::read_to_string(link.source.join("nested").join("file")).unwrap(),
+            "content"
+        );
+        assert!(link.is_valid());
+        link.unlink().expect("Failed to remove copy");
+        assert!(!link.source.exists());
+        assert!(link.target.join("file").exists());
+    }
+
+    #[test]
+    fn copy_drift() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        fs::write(&link.target, "content").expect("Failed to write target");
+        link.link(false).expect("Failed to copy target");
+        fs::write(&link.source, "edited").expect("Failed to edit copy");
+        assert!(matches!(link.status(), Status::InvalidSource(_)));
+        assert!(link.health().is_unsatisfied());
+        // Edited copies are left in place
+        link.unlink().expect("Failed to unlink");
+        assert_eq!(fs::read_to_string(&link.source).unwrap(), "edited");
+    }
+
+    #[test]
+    fn copy_target_changed() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        fs::write(&link.target, "content").expect("Failed to write target");
+        link.link(false).expect("Failed to copy target");
+        fs::write(&link.target, "changed").expect("Failed to write target");
+        assert!(matches!(link.status(), Status::Outdated));
+        assert_eq!(
+            link.plan_link(false),
+            Plan::Change("update copy".to_string())
+        );
+        link.link(false).expect("Failed to update copy");
+        assert_eq!(fs::read_to_string(&link.source).unwrap(), "changed");
+        assert!(link.is_valid());
+    }
+
+    #[test]
+    fn hardlink_file() {
+        let (_dir, link) = fixture_mode(LinkMode::Hardlink);
+        fs::write(&link.target, "content").expect("Failed to write target");
+        link.link(false).expect("Failed to create hardlink");
+        assert!(link.is_valid());
+        fs::write(&link.target, "changed").expect("Failed to write target");
+        assert!(link.is_valid());
+        link.unlink().expect("Failed to remove hardlink");
+        assert!(!link.source.exists());
+        assert!(link.target.exists());
+    }
+
+    #[test]
+    fn hardlink_wrong_file() {
+        let (_dir, link) = fixture_mode(LinkMode::Hardlink);
+        fs::write(&link.target, "content").expect("Failed to write target");
+        fs::write(&link.source, "content").expect("Failed to write source");
+        assert!(matches!(link.status(), Status::InvalidSource(_)));
+    }
+
+    #[test]
+    fn adopt_file() {
+        let (dir, link) = fixture();
+        let link = Link::new(&link.source, dir.path().join("repo").join("link.target"));
+        fs::write(&link.source, "content").expect("Failed to write source");
+        assert!(matches!(link.plan_adopt(), Plan::Change(_)));
+        link.adopt().expect("Failed to adopt file");
+        assert!(link.is_valid());
+        assert_eq!(fs::read_to_string(&link.target).unwrap(), "content");
+        // Already adopted
+        assert!(matches!(link.plan_adopt(), Plan::Fail(_)));
+        assert!(link.adopt().is_err());
+    }
+
+    #[test]
+    fn adopt_missing() {
+        let (_dir, link) = fixture();
+        assert!(matches!(link.plan_adopt(), Plan::Fail(_)));
+        assert!(link.adopt().is_err());
+    }
+
+    #[test]
+    fn link_tree_globs() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let target = dir.path().join("repo");
+        fs::create_dir_all(target.join("lua").join("plugins")).expect("Failed to create tempdir");
+        for file in [
+            "init.lua",
+            "notes.bak",
+            "lua/plugins/a.lua",
+            "lua/b.lua.bak",
+        ] {
+            File::create(target.join(file)).expect("Failed to create tempfile");
+        }
+        let tree = LinkTree {
+            source: dir.path().join("nvim"),
+            target: target.clone(),
+            include: vec!["**/*.lua".to_string(), "*.bak".to_string()],
+            exclude: vec!["notes.*".to_string()],
+            mode: LinkMode::Copy,
+            relative: None,
+            permissions: None,
+        };
+        let units = tree
+            .resolve_into_new(&mut Context::default())
+            .expect("Failed to resolve link tree");
+        assert_eq!(
+            units,
+            vec![
+                BuildUnit::Link(Link {
+                    source: dir.path().join("nvim").join("init.lua"),
+                    target: target.join("init.lua"),
+                    mode: LinkMode::Copy,
+                    relative: None,
+                    permissions: None,
+                    stamps: Stamps::default(),
+                }),
+                BuildUnit::Link(Link {
+                    source: dir.path().join("nvim").join("lua/plugins/a.lua"),
+                    target: target.join("lua/plugins/a.lua"),
+                    mode: LinkMode::Copy,
+                    relative: None,
+                    permissions: None,
+                    stamps: Stamps::default(),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn link_tree_missing_target() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let tree = LinkTree {
+            source: dir.path().join("nvim"),
+            target: dir.path().join("repo"),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            mode: LinkMode::Symlink,
+            relative: None,
+            permissions: None,
+        };
+        // Resolving to no links would prune the links of the missing files
+        assert!(tree.resolve_into_new(&mut Context::default()).is_err());
+    }
+
+    #[test]
+    fn relative_path_parents() {
+        assert_eq!(
+            relative_path(
+                Path::new("/home/user/.config"),
+                Path::new("/home/user/repo/file")
+            ),
+            PathBuf::from("../repo/file")
+        );
+        assert_eq!(
+            relative_path(Path::new("/home/user"), Path::new("/home/user/file")),
+            PathBuf::from("file")
+        );
+    }
+
+    #[test]
+    fn link_relative() {
+        let (dir, link) = fixture();
+        let link = Link {
+            source: dir.path().join("home").join("link.source"),
+            relative: Some(true),
+            ..link
+        };
+        File::create(&link.target).expect("Failed to create tempfile");
+        link.link(false).expect("Failed to create link");
+        assert_eq!(
+            link.source.read_link().expect("Failed to read link"),
+            PathBuf::from("../link.target")
+        );
+        assert!(link.is_valid());
+        // Absolute links are also valid
+        let absolute = Link {
+            relative: None,
+            ..link.clone()
+        };
+        assert!(absolute.is_valid());
+        link.unlink().expect("Failed to remove link");
+        assert!(!link.source.exists());
+    }
+
+    #[test]
+    fn resolve_relative_default() {
+        let mut context = Context::default();
+        context.relative_links = true;
+        let units = Link::new("source", "target")
+            .resolve_into_new(&mut context)
+            .expect("Failed to resolve link");
+        assert!(matches!(
+            &units[..],
+            [BuildUnit::Link(Link {
+                relative: Some(true),
+                ..
+            })]
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn link_permissions() {
+        let (_dir, link) = fixture_mode(LinkMode::Copy);
+        let link = Link {
+            permissions: Some(serde_yaml::from_str("\"0600\"").unwrap()),
+            ..link
+        };
+        fs::write(&link.target, "content").expect("Failed to write target");
+        link.link(false).expect("Failed to copy target");
+        assert_eq!(link.plan_link(false), Plan::Satisfied);
+        assert!(!link.health().is_unsatisfied());
+        let wrong = Link {
+            permissions: Some(serde_yaml::from_str("\"0644\"").unwrap()),
+            ..link.clone()
+        };
+        assert_eq!(
+            wrong.health(),
+            Health::Unsatisfied("mode 0600, expected 0644".to_string())
+        );
+        assert_eq!(
+            wrong.plan_link(false),
+            Plan::Change("set mode 0644".to_string())
+        );
+    }
+
     #[test]
     fn clean_invalid_source() {
         let (_dir, link) = fixture();
@@ -238,6 +1038,33 @@ mod tests {
         link.link(false).expect("Failed to apply link");
     }
 
+    #[test]
+    fn clean_backup_directory() {
+        let (_dir, link) = fixture();
+        File::create(&link.target).expect("Failed to create tempfile");
+        fs::create_dir_all(link.source.join("nested")).expect("Failed to create tempdir");
+        link.link(true).expect("Failed to apply link");
+        assert!(link.is_valid());
+        let backup = backup::latest(&link.source)
+            .unwrap()
+            .expect("Missing backup");
+        assert!(backup.join("nested").is_dir());
+    }
+
+    #[test]
+    fn restore_backup() {
+        let (_dir, link) = fixture();
+        File::create(&link.target).expect("Failed to create tempfile");
+        fs::write(&link.source, "original").expect("Failed to write source");
+        assert_eq!(link.plan_restore(), Plan::Satisfied);
+        link.link(true).expect("Failed to apply link");
+        assert!(matches!(link.plan_restore(), Plan::Change(_)));
+        link.restore().expect("Failed to restore backup");
+        assert!(!link.is_valid());
+        assert_eq!(fs::read_to_string(&link.source).unwrap(), "original");
+        assert_eq!(backup::latest(&link.source).unwrap(), None);
+    }
+
     #[test]
     fn clean_broken_link() {
         let (dir, link) = fixture();
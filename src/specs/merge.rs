@@ -0,0 +1,572 @@
+use crate::{
+    digest,
+    specs::{BuildUnit, Context, Health, Plan, Resolve},
+    state::Stamps,
+    yaml_example_doc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    ffi::OsStr,
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Structured file format
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Infer the format from the extension of `path`
+    fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("json") => Ok(Self::Json),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            Some("toml") => Ok(Self::Toml),
+            _ => bail!("Unable to infer structured file format: {path:?}"),
+        }
+    }
+
+    fn parse(self, text: &str) -> Result<Value> {
+        if text.trim().is_empty() {
+            return Ok(Value::Object(serde_json::Map::new()));
+        }
+        match self {
+            Self::Json => serde_json::from_str(text).with_context(|| match is_jsonc(text) {
+                true => "Failed to parse JSON (comments and trailing commas are not supported)",
+                false => "Failed to parse JSON",
+            }),
+            Self::Yaml => serde_yaml::from_str(text).context("Failed to parse YAML"),
+            Self::Toml => toml::from_str(text).context("Failed to parse TOML"),
+        }
+    }
+
+    fn render(self, value: &Value) -> Result<String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(value)
+                .map(|json| json + "\n")
+                .context("Failed to serialize JSON"),
+            Self::Yaml => serde_yaml::to_string(value).context("Failed to serialize YAML"),
+            Self::Toml => toml::to_string_pretty(value).context("Failed to serialize TOML"),
+        }
+    }
+}
+
+/// Check if `text` looks like JSON with comments or trailing commas
+fn is_jsonc(text: &str) -> bool {
+    text.contains("//")
+        || text.contains("/*")
+        || Regex::new(r",\s*[}\]]").is_ok_and(|re| re.is_match(text))
+}
+
+/// Deep merge `fragment` into `target` (mappings are merged, other values are replaced)
+fn merge(target: &mut Value, fragment: &Value) {
+    match (target, fragment) {
+        (Value::Object(target), Value::Object(fragment)) => {
+            for (key, value) in fragment {
+                merge(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (target, fragment) => *target = fragment.clone(),
+    }
+}
+
+/// Check if every key of `fragment` is set to the same value in `target`
+fn contains(target: &Value, fragment: &Value) -> bool {
+    match (target, fragment) {
+        (Value::Object(target), Value::Object(fragment)) => fragment.iter().all(|(key, value)| {
+            target
+                .get(key)
+                .is_some_and(|existing| contains(existing, value))
+        }),
+        (target, fragment) => target == fragment,
+    }
+}
+
+/// Values of `target` that merging `fragment` would replace
+fn replaced(target: &Value, fragment: &Value) -> Value {
+    match (target, fragment) {
+        (Value::Object(target), Value::Object(fragment)) => Value::Object(
+            fragment
+                .iter()
+                .filter_map(|(key, value)| {
+                    target
+                        .get(key)
+                        .map(|existing| (key.clone(), replaced(existing, value)))
+                })
+                .collect(),
+        ),
+        (target, _) => target.clone(),
+    }
+}
+
+/// Undo a merge of `fragment` into `target` that replaced the `prior` values.
+///
+/// Keys that no longer hold the merged value are kept, and mappings left empty are removed.
+fn strip(target: &mut Value, fragment: &Value, prior: Option<&Value>) {
+    let (Value::Object(target), Value::Object(fragment)) = (target, fragment) else {
+        return;
+    };
+    for (key, value) in fragment {
+        let prior = prior.and_then(|prior| prior.get(key));
+        let remove = match (target.get_mut(key), value, prior) {
+            (
+                Some(existing @ Value::Object(_)),
+                Value::Object(_),
+                None | Some(Value::Object(_)),
+            ) => {
+                strip(existing, value, prior);
+                prior.is_none() && existing.as_object().is_some_and(serde_json::Map::is_empty)
+            }
+            (Some(existing), _, Some(prior)) if existing == value => {
+                *existing = prior.clone();
+                false
+            }
+            (Some(existing), _, None) => existing == value,
+            _ => false,
+        };
+        if remove {
+            target.shift_remove(key);
+        }
+    }
+}
+
+/// Replace keys in every string of `value`
+fn parse_value(context: &Context, value: serde_yaml::Value) -> Result<serde_yaml::Value> {
+    Ok(match value {
+        serde_yaml::Value::String(s) => serde_yaml::Value::String(context.parse_str(&s)?),
+        serde_yaml::Value::Sequence(seq) => serde_yaml::Value::Sequence(
+            seq.into_iter()
+                .map(|item| parse_value(context, item))
+                .collect::<Result<_>>()?,
+        ),
+        serde_yaml::Value::Mapping(map) => serde_yaml::Value::Mapping(
+            map.into_iter()
+                .map(|(key, item)| Ok((key, parse_value(context, item)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other,
+    })
+}
+
+/// Keys merged into a structured (JSON/YAML/TOML) file and the values they replaced
+#[derive(Debug, Deserialize, Serialize)]
+struct Stamp {
+    merged: Value,
+    prior: Value,
+}
+
+/// Keys merged into a structured (JSON/YAML/TOML) file
+///
+/// Other keys in the file are preserved. The fragment is either inline
+/// [`content`][Self::content] or a [`source`][Self::source] file. On removal,
+/// keys that still hold the merged value are restored to the value they replaced.
+/// Each fragment is tracked on its own, so several units may merge into one file.
+#[doc = yaml_example_doc!("merge.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Merge {
+    /// Path of the structured file
+    path: PathBuf,
+    /// Format of the file (inferred from the extension by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<Format>,
+    /// Inline fragment to merge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content: Option<serde_yaml::Value>,
+    /// Path of a file containing the fragment to merge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<PathBuf>,
+    /// Location of the keys recorded when the file is written
+    #[serde(skip)]
+    stamps: Stamps,
+}
+
+impl Merge {
+    fn format(&self) -> Result<Format> {
+        self.format
+            .map_or_else(|| Format::from_path(&self.path), Ok)
+    }
+
+    /// Fragment to merge into the file
+    fn fragment(&self) -> Result<Value> {
+        match (&self.content, &self.source) {
+            (Some(content), _) => {
+                serde_json::to_value(content).context("Failed to convert merge content")
+            }
+            (None, Some(source)) => fs::read_to_string(source)
+                .with_context(|| format!("Failed to read merge source: {}", source.display()))
+                .and_then(|text| Format::from_path(source)?.parse(&text)),
+            (None, None) => bail!("Merge requires content or source"),
+        }
+    }
+
+    /// Current file content (empty if the file does not exist)
+    fn read(&self) -> Result<Value> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read file: {self}")),
+        };
+        self.format()?
+            .parse(&text)
+            .with_context(|| format!("Failed to parse file: {self}"))
+    }
+
+    /// Keep the merged keys in `stamps`
+    pub fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// Path of the file and the [`source`][Self::source] (or content digest) of the fragment
+    pub fn key(&self) -> (&Path, String) {
+        let fragment = match (&self.source, &self.content) {
+            (Some(source), _) => source.display().to_string(),
+            (None, content) => digest::sha256(
+                serde_yaml::to_string(content)
+                    .unwrap_or_default()
+                    .as_bytes(),
+            ),
+        };
+        (&self.path, fragment)
+    }
+
+    /// Path of the stamp that records the merged keys
+    fn stamp_path(&self) -> Result<PathBuf> {
+        let (path, fragment) = self.key();
+        self.stamps
+            .path("merge", &format!("{} {fragment}", path.display()))
+    }
+
+    /// Keys merged by the last write (if any)
+    fn stamp(&self) -> Result<Option<Stamp>> {
+        match fs::read_to_string(self.stamp_path()?) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .context("Failed to parse merge stamp"),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).context("Failed to read merge stamp"),
+        }
+    }
+
+    fn write_stamp(&self, stamp: &Stamp) -> Result<()> {
+        let path = self.stamp_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(stamp)?).context("Failed to write merge stamp")
+    }
+
+    fn write_value(&self, value: &Value) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, self.format()?.render(value)?)
+            .with_context(|| format!("Failed to write file: {self}"))
+    }
+
+    /// Return true if every merged key is up to date
+    pub fn is_merged(&self) -> bool {
+        match (self.read(), self.fragment()) {
+            (Ok(current), Ok(fragment)) => contains(&current, &fragment),
+            _ => false,
+        }
+    }
+
+    /// Report whether the merged keys are up to date
+    pub fn health(&self) -> Health {
+        match (self.read(), self.fragment()) {
+            _ if !self.path.exists() => Health::Unsatisfied("missing".to_string()),
+            (Ok(current), Ok(fragment)) if contains(&current, &fragment) => {
+                Health::Satisfied("keys merged".to_string())
+            }
+            (Ok(_), Ok(_)) => Health::Unsatisfied("keys differ".to_string()),
+            (Err(e), _) | (_, Err(e)) => Health::Unsatisfied(format!("{e:#}")),
+        }
+    }
+
+    /// Merge the keys into the file
+    pub fn write(&self) -> Result<()> {
+        let mut current = self.read()?;
+        let fragment = self.fragment()?;
+        if contains(&current, &fragment) {
+            return Ok(());
+        }
+        log::info!("Merging keys into {self}");
+        // Undo the previous merge so that the values it replaced are recorded again
+        if let Some(stamp) = self.stamp()? {
+            strip(&mut current, &stamp.merged, Some(&stamp.prior));
+        }
+        let prior = replaced(&current, &fragment);
+        merge(&mut current, &fragment);
+        self.write_value(&current)?;
+        self.write_stamp(&Stamp {
+            merged: fragment,
+            prior,
+        })
+    }
+
+    /// Describe the changes that [`Self::write`] would make
+    pub fn plan_write(&self) -> Plan {
+        match (self.read(), self.fragment()) {
+            (Ok(current), Ok(fragment)) if contains(&current, &fragment) => Plan::Satisfied,
+            (Ok(_), Ok(_)) => Plan::Change("merge keys".to_string()),
+            (Err(e), _) | (_, Err(e)) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+
+    /// File content with the merged keys removed (if it changed)
+    fn stripped(&self) -> Result<Option<Value>> {
+        let Some(stamp) = self.stamp()? else {
+            return Ok(None);
+        };
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let mut current = self.read()?;
+        let original = current.clone();
+        strip(&mut current, &stamp.merged, Some(&stamp.prior));
+        Ok((current != original).then_some(current))
+    }
+
+    /// Remove the merged keys and restore the values they replaced
+    pub fn remove(&self) -> Result<()> {
+        if let Some(current) = self.stripped()? {
+            log::info!("Removing merged keys from {self}");
+            self.write_value(&current)?;
+        }
+        match fs::remove_file(self.stamp_path()?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).context("Failed to remove merge stamp")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match self.stripped() {
+            Ok(Some(_)) => Plan::Change("remove merged keys".to_string()),
+            Ok(None) => Plan::Satisfied,
+            Err(e) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+}
+
+impl fmt::Display for Merge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source {
+            Some(ref source) => write!(f, "{} -> {}", source.display(), self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+impl Resolve for Merge {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        if self.content.is_some() == self.source.is_some() {
+            bail!("Merge requires exactly one of content or source: {self}");
+        }
+        Ok(BuildUnit::Merge(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            content: self
+                .content
+                .map(|content| parse_value(context, content))
+                .transpose()?,
+            source: self
+                .source
+                .map(|source| context.parse_path(source.to_str().unwrap_or("")))
+                .transpose()?
+                .map(PathBuf::from),
+            stamps: context.stamps.clone(),
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn fixture(file: &str, existing: Option<&str>, content: &str) -> (tempfile::TempDir, Merge) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let path = dir.path().join(file);
+        if let Some(existing) = existing {
+            fs::write(&path, existing).expect("Failed to write file");
+        }
+        let unit = Merge {
+            path,
+            format: None,
+            content: Some(serde_yaml::from_str(content).unwrap()),
+            source: None,
+            stamps: Stamps::new(dir.path().join("stamps")),
+        };
+        (dir, unit)
+    }
+
+    #[test]
+    fn deep_merge_and_strip() {
+        let mut target = json!({ "a": 1, "nested": { "b": 2, "c": 3 } });
+        let fragment = json!({ "nested": { "c": 4, "d": { "e": 5 } }, "f": [1] });
+        merge(&mut target, &fragment);
+        assert_eq!(
+            target,
+            json!({ "a": 1, "nested": { "b": 2, "c": 4, "d": { "e": 5 } }, "f": [1] })
+        );
+        assert!(contains(&target, &fragment));
+        strip(&mut target, &fragment, None);
+        assert_eq!(target, json!({ "a": 1, "nested": { "b": 2 } }));
+        assert!(!contains(&target, &fragment));
+    }
+
+    #[test]
+    fn strip_restores_prior() {
+        let original = json!({ "a": 1, "nested": { "b": 2, "c": 3 } });
+        let fragment = json!({ "a": 5, "nested": { "c": 4, "d": 6 } });
+        let prior = replaced(&original, &fragment);
+        assert_eq!(prior, json!({ "a": 1, "nested": { "c": 3 } }));
+        let mut target = original.clone();
+        merge(&mut target, &fragment);
+        strip(&mut target, &fragment, Some(&prior));
+        assert_eq!(target, original);
+        // Keys changed after the merge are kept
+        merge(&mut target, &fragment);
+        target["nested"]["d"] = json!(7);
+        strip(&mut target, &fragment, Some(&prior));
+        assert_eq!(
+            target,
+            json!({ "a": 1, "nested": { "b": 2, "c": 3, "d": 7 } })
+        );
+    }
+
+    #[test]
+    fn merge_json() {
+        let (_dir, unit) = fixture(
+            "settings.json",
+            Some(r#"{ "window.zoomLevel": 1, "editor.fontSize": 12 }"#),
+            "editor.fontSize: 14",
+        );
+        assert!(unit.health().is_unsatisfied());
+        assert_eq!(unit.plan_write(), Plan::Change("merge keys".to_string()));
+        unit.write().expect("Failed to merge keys");
+        assert_eq!(
+            serde_json::from_str::<Value>(&fs::read_to_string(&unit.path).unwrap()).unwrap(),
+            json!({ "window.zoomLevel": 1, "editor.fontSize": 14 })
+        );
+        assert!(unit.is_merged());
+        assert_eq!(unit.plan_write(), Plan::Satisfied);
+        unit.remove().expect("Failed to remove keys");
+        assert_eq!(
+            serde_json::from_str::<Value>(&fs::read_to_string(&unit.path).unwrap()).unwrap(),
+            json!({ "window.zoomLevel": 1, "editor.fontSize": 12 })
+        );
+        assert_eq!(unit.plan_remove(), Plan::Satisfied);
+    }
+
+    #[test]
+    fn keep_edited_keys() {
+        let (_dir, unit) = fixture(
+            "config.yaml",
+            Some(
+                "keep: true
+",
+            ),
+            "theme: dark",
+        );
+        unit.write().expect("Failed to merge keys");
+        fs::write(&unit.path, "keep: true\ntheme: light\n").unwrap();
+        assert_eq!(unit.plan_remove(), Plan::Satisfied);
+        unit.remove().expect("Failed to remove keys");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "keep: true\ntheme: light\n"
+        );
+    }
+
+    #[test]
+    fn merge_two_fragments() {
+        let (_dir, first) = fixture("config.yaml", Some("keep: true\n"), "a: 1");
+        let second = Merge {
+            content: Some(serde_yaml::from_str("b: 2").unwrap()),
+            ..first.clone()
+        };
+        assert!(!BuildUnit::Merge(first.clone()).is_same(&BuildUnit::Merge(second.clone())));
+        first.write().expect("Failed to merge keys");
+        second.write().expect("Failed to merge keys");
+        let merged = "keep: true\na: 1\nb: 2\n";
+        assert_eq!(fs::read_to_string(&first.path).unwrap(), merged);
+        // A second install changes nothing
+        assert_eq!(first.plan_write(), Plan::Satisfied);
+        assert_eq!(second.plan_write(), Plan::Satisfied);
+        first.write().expect("Failed to merge keys");
+        second.write().expect("Failed to merge keys");
+        assert_eq!(fs::read_to_string(&first.path).unwrap(), merged);
+        first.remove().expect("Failed to remove keys");
+        assert_eq!(
+            fs::read_to_string(&first.path).unwrap(),
+            "keep: true\nb: 2\n"
+        );
+    }
+
+    #[test]
+    fn reject_jsonc() {
+        let (_dir, unit) = fixture(
+            "settings.json",
+            Some("{\n  // comment\n  \"a\": 1,\n}\n"),
+            "b: 2",
+        );
+        let error = format!("{:#}", unit.write().unwrap_err());
+        assert!(error.contains("comments and trailing commas are not supported"));
+    }
+
+    #[test]
+    fn merge_toml_missing_file() {
+        let (_dir, unit) = fixture("config.toml", None, "core: { editor: vim }");
+        assert_eq!(unit.health(), Health::Unsatisfied("missing".to_string()));
+        unit.write().expect("Failed to merge keys");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "[core]\neditor = \"vim\"\n"
+        );
+        assert_eq!(
+            unit.plan_remove(),
+            Plan::Change("remove merged keys".to_string())
+        );
+    }
+
+    #[test]
+    fn merge_source_file() {
+        let (dir, unit) = fixture("config.yaml", Some("keep: true\n"), "{}");
+        let source = dir.path().join("fragment.json");
+        fs::write(&source, r#"{ "theme": "dark" }"#).unwrap();
+        let unit = Merge {
+            content: None,
+            source: Some(source),
+            ..unit
+        };
+        unit.write().expect("Failed to merge keys");
+        assert_eq!(
+            fs::read_to_string(&unit.path).unwrap(),
+            "keep: true\ntheme: dark\n"
+        );
+    }
+
+    #[test]
+    fn resolve_requires_one_fragment() {
+        let (_dir, unit) = fixture("config.yaml", None, "{}");
+        let unit = Merge {
+            source: Some(PathBuf::from("fragment.yaml")),
+            ..unit
+        };
+        assert!(unit.resolve(&mut Context::default()).is_err());
+    }
+}
@@ -1,23 +1,43 @@
+mod directory;
+mod download;
 mod dynamic;
+mod file;
+mod include;
+mod line_in_file;
 mod link;
+mod merge;
 mod package;
+mod permissions;
+mod release;
 mod repo;
 mod shell;
+mod template;
 
+pub use self::link::Link;
 pub use self::package::PackageManager;
-pub use self::shell::Hook;
+pub use self::shell::{Hook, ShellCommand};
 use self::{
+    directory::Directory,
+    download::Download,
     dynamic::{Case, Matrix, Vars},
-    link::Link,
+    file::File,
+    include::Include,
+    line_in_file::LineInFile,
+    link::LinkTree,
+    merge::Merge,
     package::Package,
+    permissions::Chmod,
+    release::GithubRelease,
     repo::Repo,
     shell::ShellHook,
+    template::Template,
 };
 
-use crate::context::Context;
+use crate::{context::Context, state::Stamps};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 pub trait Resolve {
     fn resolve(self, context: &mut Context) -> Result<BuildUnit>;
@@ -66,6 +86,16 @@ pub enum BuildUnitKind {
     Package,
     #[clap(name = "package_manager")]
     PackageManager,
+    Template,
+    Chmod,
+    Directory,
+    File,
+    #[clap(name = "line_in_file")]
+    LineInFile,
+    Merge,
+    Download,
+    #[clap(name = "github_release")]
+    GithubRelease,
 }
 
 /// Single resolved build step
@@ -76,17 +106,299 @@ pub enum BuildUnit {
     Hook(ShellHook),
     Package(Package),
     PackageManager(PackageManager),
+    Template(Template),
+    Chmod(Chmod),
+    Directory(Directory),
+    File(File),
+    LineInFile(LineInFile),
+    Merge(Merge),
+    Download(Download),
+    GithubRelease(GithubRelease),
 }
 
 impl BuildUnit {
-    pub fn included_in(&self, units: &[BuildUnitKind]) -> bool {
-        units.contains(&match self {
+    pub fn kind(&self) -> BuildUnitKind {
+        match self {
             Self::Repo(_) => BuildUnitKind::Repo,
             Self::Link(_) => BuildUnitKind::Link,
             Self::Hook(_) => BuildUnitKind::Hook,
             Self::Package(_) => BuildUnitKind::Package,
             Self::PackageManager(_) => BuildUnitKind::PackageManager,
-        })
+            Self::Template(_) => BuildUnitKind::Template,
+            Self::Chmod(_) => BuildUnitKind::Chmod,
+            Self::Directory(_) => BuildUnitKind::Directory,
+            Self::File(_) => BuildUnitKind::File,
+            Self::LineInFile(_) => BuildUnitKind::LineInFile,
+            Self::Merge(_) => BuildUnitKind::Merge,
+            Self::Download(_) => BuildUnitKind::Download,
+            Self::GithubRelease(_) => BuildUnitKind::GithubRelease,
+        }
+    }
+
+    pub fn included_in(&self, units: &[BuildUnitKind]) -> bool {
+        units.contains(&self.kind())
+    }
+
+    /// Identity of the unit that is unaffected by changes to its other attributes
+    fn identity(&self) -> String {
+        match self {
+            Self::Repo(repo) => repo.to_string(),
+            Self::Link(link) => link.source().display().to_string(),
+            Self::Hook(hook) => hook.to_string(),
+            Self::Package(package) => package.to_string(),
+            Self::PackageManager(manager) => manager.to_string(),
+            Self::Template(template) => template.path().display().to_string(),
+            Self::Chmod(chmod) => chmod.path().display().to_string(),
+            Self::Directory(directory) => directory.path().display().to_string(),
+            Self::File(file) => file.path().display().to_string(),
+            Self::LineInFile(line) => {
+                let (path, key) = line.key();
+                format!("{} {key}", path.display())
+            }
+            Self::Merge(merge) => {
+                let (path, fragment) = merge.key();
+                format!("{} {fragment}", path.display())
+            }
+            Self::Download(download) => download.path().display().to_string(),
+            Self::GithubRelease(release) => release.path().display().to_string(),
+        }
+    }
+
+    /// Check if `other` manages the same thing as this unit
+    pub fn is_same(&self, other: &Self) -> bool {
+        self.kind() == other.kind() && self.identity() == other.identity()
+    }
+
+    /// Keep the records written by the unit in `stamps` (for units that were not resolved)
+    pub fn set_stamps(&mut self, stamps: &Stamps) {
+        match self {
+            Self::Link(link) => link.set_stamps(stamps.clone()),
+            Self::Template(template) => template.set_stamps(stamps.clone()),
+            Self::Merge(merge) => merge.set_stamps(stamps.clone()),
+            Self::Download(download) => download.set_stamps(stamps.clone()),
+            Self::GithubRelease(release) => release.set_stamps(stamps.clone()),
+            _ => (),
+        }
+    }
+
+    /// Check if running `hook` acts on the unit
+    pub fn affected_by(&self, hook: &Hook) -> bool {
+        match self {
+            Self::Hook(inner) => inner.applies(hook),
+            Self::Repo(_) | Self::PackageManager(_) | Self::Chmod(_) => {
+                matches!(hook, Hook::Install)
+            }
+            Self::Link(_)
+            | Self::Package(_)
+            | Self::Template(_)
+            | Self::File(_)
+            | Self::Directory(_)
+            | Self::LineInFile(_)
+            | Self::Merge(_)
+            | Self::Download(_)
+            | Self::GithubRelease(_) => {
+                matches!(hook, Hook::Install | Hook::Uninstall)
+            }
+        }
+    }
+
+    /// Check the current state of the unit for `yurt status`
+    pub fn health(&self, context: &Context) -> Health {
+        match self {
+            Self::Repo(repo) => repo.health(),
+            Self::Link(link) => link.health(),
+            Self::Hook(_) => Health::Unknown,
+            Self::Package(package) => package.health(context),
+            Self::PackageManager(manager) => manager.health(),
+            Self::Template(template) => template.health(),
+            Self::Chmod(chmod) => chmod.health(),
+            Self::Directory(directory) => directory.health(),
+            Self::File(file) => file.health(),
+            Self::LineInFile(lines) => lines.health(),
+            Self::Merge(merge) => merge.health(),
+            Self::Download(download) => download.health(),
+            Self::GithubRelease(release) => release.health(),
+        }
+    }
+
+    /// Apply the unit for `yurt install`
+    pub fn install(&self, context: &Context, clean: bool) -> Result<()> {
+        match self {
+            Self::Repo(repo) => repo.require().map(drop),
+            Self::Link(link) => link.link(clean),
+            Self::Hook(hook) => hook.exec_for(&Hook::Install),
+            Self::Package(package) => package.install(context),
+            Self::PackageManager(manager) => manager.require(),
+            Self::Template(template) => template.write(),
+            Self::Chmod(chmod) => chmod.apply(),
+            Self::Directory(directory) => directory.create(),
+            Self::File(file) => file.write(),
+            Self::LineInFile(lines) => lines.write(),
+            Self::Merge(merge) => merge.write(),
+            Self::Download(download) => download.install(),
+            Self::GithubRelease(release) => release.install(),
+        }
+    }
+
+    /// Describe the changes that [`Self::install`] would make
+    pub fn plan_install(&self, context: &Context, clean: bool) -> Plan {
+        match self {
+            Self::Repo(repo) => repo.plan_require(),
+            Self::Link(link) => link.plan_link(clean),
+            Self::Hook(hook) => hook.plan_for(&Hook::Install),
+            Self::Package(package) => package.plan_install(context),
+            Self::PackageManager(manager) => manager.plan_require(),
+            Self::Template(template) => template.plan_write(),
+            Self::Chmod(chmod) => chmod.plan_apply(),
+            Self::Directory(directory) => directory.plan_create(),
+            Self::File(file) => file.plan_write(),
+            Self::LineInFile(lines) => lines.plan_write(),
+            Self::Merge(merge) => merge.plan_write(),
+            Self::Download(download) => download.plan_install(),
+            Self::GithubRelease(release) => release.plan_install(),
+        }
+    }
+
+    /// Revert the unit for `yurt uninstall`
+    pub fn uninstall(&self, context: &Context) -> Result<()> {
+        match self {
+            Self::Link(link) => link.unlink(),
+            Self::Hook(hook) => hook.exec_for(&Hook::Uninstall),
+            Self::Package(package) => package.uninstall(context),
+            Self::Template(template) => template.remove(),
+            Self::Directory(directory) => directory.remove(),
+            Self::File(file) => file.remove(),
+            Self::LineInFile(lines) => lines.remove(),
+            Self::Merge(merge) => merge.remove(),
+            Self::Download(download) => download.remove(),
+            Self::GithubRelease(release) => release.remove(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::uninstall`] would make
+    pub fn plan_uninstall(&self, context: &Context) -> Plan {
+        match self {
+            Self::Link(link) => link.plan_unlink(),
+            Self::Hook(hook) => hook.plan_for(&Hook::Uninstall),
+            Self::Package(package) => package.plan_uninstall(context),
+            Self::Template(template) => template.plan_remove(),
+            Self::Directory(directory) => directory.plan_remove(),
+            Self::File(file) => file.plan_remove(),
+            Self::LineInFile(lines) => lines.plan_remove(),
+            Self::Merge(merge) => merge.plan_remove(),
+            Self::Download(download) => download.plan_remove(),
+            Self::GithubRelease(release) => release.plan_remove(),
+            _ => Plan::Skip,
+        }
+    }
+
+    /// Restore backups of files that were replaced by the unit
+    pub fn restore(&self) -> Result<()> {
+        match self {
+            Self::Link(link) => link.restore(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::restore`] would make
+    pub fn plan_restore(&self) -> Plan {
+        match self {
+            Self::Link(link) => link.plan_restore(),
+            _ => Plan::Skip,
+        }
+    }
+
+    /// Run the unit for `yurt hook <hook>`
+    pub fn hook(&self, hook: &Hook) -> Result<()> {
+        match self {
+            Self::Hook(inner) => inner.exec_for(hook),
+            _ => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::hook`] would make
+    pub fn plan_hook(&self, hook: &Hook) -> Plan {
+        match self {
+            Self::Hook(inner) => inner.plan_for(hook),
+            _ => Plan::Skip,
+        }
+    }
+}
+
+impl fmt::Display for BuildUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Repo(repo) => write!(f, "repo {repo}"),
+            Self::Link(link) => write!(f, "link {link}"),
+            Self::Hook(hook) => write!(f, "hook {hook}"),
+            Self::Package(package) => write!(f, "package {package}"),
+            Self::PackageManager(manager) => write!(f, "package_manager {manager}"),
+            Self::Template(template) => write!(f, "template {template}"),
+            Self::Chmod(chmod) => write!(f, "chmod {chmod}"),
+            Self::Directory(directory) => write!(f, "directory {directory}"),
+            Self::File(file) => write!(f, "file {file}"),
+            Self::LineInFile(lines) => write!(f, "line_in_file {lines}"),
+            Self::Merge(merge) => write!(f, "merge {merge}"),
+            Self::Download(download) => write!(f, "download {download}"),
+            Self::GithubRelease(release) => write!(f, "github_release {release}"),
+        }
+    }
+}
+
+/// Expected outcome of running an action on a [`BuildUnit`]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Plan {
+    /// Unit is already in the desired state
+    Satisfied,
+    /// Action does not apply to the unit
+    Skip,
+    /// Description of the change that would be made
+    Change(String),
+    /// Reason that the action would fail
+    Fail(String),
+}
+
+/// Current state of a [`BuildUnit`] on the local system
+#[derive(Debug, PartialEq, Eq)]
+pub enum Health {
+    /// Unit is applied (with details)
+    Satisfied(String),
+    /// Unit is not applied (with reason)
+    Unsatisfied(String),
+    /// Unit does not have a state to check
+    Unknown,
+}
+
+impl Health {
+    pub fn is_unsatisfied(&self) -> bool {
+        matches!(self, Self::Unsatisfied(_))
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Satisfied(_) => "ok",
+            Self::Unsatisfied(_) => "unsatisfied",
+            Self::Unknown => "-",
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::Satisfied(detail) | Self::Unsatisfied(detail) => detail,
+            Self::Unknown => "",
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Satisfied => write!(f, "already satisfied"),
+            Self::Skip => write!(f, "skipped"),
+            Self::Change(change) => write!(f, "would {change}"),
+            Self::Fail(reason) => write!(f, "would fail: {reason}"),
+        }
     }
 }
 
@@ -100,16 +412,36 @@ pub enum BuildSpec {
     Case(Case<Vec<Self>>),
     /// [`!matrix`][Matrix<Vec<Self>>]
     Matrix(Matrix<Vec<Self>>),
+    /// [`!include`][Include]
+    Include(Include),
     /// [`!repo`][Repo]
     Repo(Repo),
     /// [`!link`][Link]
     Link(Link),
+    /// [`!link_tree`][LinkTree]
+    LinkTree(LinkTree),
     /// [`!hook`][ShellHook]
     Hook(ShellHook),
     /// [`!package`][Package]
     Package(Package),
     /// [`!package_manager`][PackageManager]
     PackageManager(PackageManager),
+    /// [`!template`][Template]
+    Template(Template),
+    /// [`!chmod`][Chmod]
+    Chmod(Chmod),
+    /// [`!directory`][Directory]
+    Directory(Directory),
+    /// [`!file`][File]
+    File(File),
+    /// [`!line_in_file`][LineInFile]
+    LineInFile(LineInFile),
+    /// [`!merge`][Merge]
+    Merge(Merge),
+    /// [`!download`][Download]
+    Download(Download),
+    /// [`!github_release`][GithubRelease]
+    GithubRelease(GithubRelease),
 }
 
 impl From<BuildUnit> for BuildSpec {
@@ -120,21 +452,62 @@ impl From<BuildUnit> for BuildSpec {
             BuildUnit::Hook(hook) => Self::Hook(hook),
             BuildUnit::Package(package) => Self::Package(package),
             BuildUnit::PackageManager(manager) => Self::PackageManager(manager),
+            BuildUnit::Template(template) => Self::Template(template),
+            BuildUnit::Chmod(chmod) => Self::Chmod(chmod),
+            BuildUnit::Directory(directory) => Self::Directory(directory),
+            BuildUnit::File(file) => Self::File(file),
+            BuildUnit::LineInFile(lines) => Self::LineInFile(lines),
+            BuildUnit::Merge(merge) => Self::Merge(merge),
+            BuildUnit::Download(download) => Self::Download(download),
+            BuildUnit::GithubRelease(release) => Self::GithubRelease(release),
         }
     }
 }
 
+impl TryFrom<BuildSpec> for BuildUnit {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: BuildSpec) -> Result<Self> {
+        Ok(match spec {
+            BuildSpec::Repo(repo) => Self::Repo(repo),
+            BuildSpec::Link(link) => Self::Link(link),
+            BuildSpec::Hook(hook) => Self::Hook(hook),
+            BuildSpec::Package(package) => Self::Package(package),
+            BuildSpec::PackageManager(manager) => Self::PackageManager(manager),
+            BuildSpec::Template(template) => Self::Template(template),
+            BuildSpec::Chmod(chmod) => Self::Chmod(chmod),
+            BuildSpec::Directory(directory) => Self::Directory(directory),
+            BuildSpec::File(file) => Self::File(file),
+            BuildSpec::LineInFile(lines) => Self::LineInFile(lines),
+            BuildSpec::Merge(merge) => Self::Merge(merge),
+            BuildSpec::Download(download) => Self::Download(download),
+            BuildSpec::GithubRelease(release) => Self::GithubRelease(release),
+            _ => bail!("Build spec is not a build unit: {spec:?}"),
+        })
+    }
+}
+
 impl ResolveInto for BuildSpec {
     fn resolve_into(self, context: &mut Context, output: &mut Vec<BuildUnit>) -> Result<()> {
         match self {
             Self::Vars(v) => v.resolve_into(context, output),
             Self::Case(v) => v.resolve_into(context, output),
             Self::Matrix(m) => m.resolve_into(context, output),
+            Self::Include(i) => i.resolve_into(context, output),
             Self::Repo(r) => r.resolve_into(context, output),
             Self::Link(v) => v.resolve_into(context, output),
+            Self::LinkTree(t) => t.resolve_into(context, output),
             Self::Hook(s) => s.resolve_into(context, output),
             Self::Package(p) => p.resolve_into(context, output),
             Self::PackageManager(m) => m.resolve_into(context, output),
+            Self::Template(t) => t.resolve_into(context, output),
+            Self::Chmod(chmod) => chmod.resolve_into(context, output),
+            Self::Directory(directory) => directory.resolve_into(context, output),
+            Self::File(file) => file.resolve_into(context, output),
+            Self::LineInFile(lines) => lines.resolve_into(context, output),
+            Self::Merge(merge) => merge.resolve_into(context, output),
+            Self::Download(download) => download.resolve_into(context, output),
+            Self::GithubRelease(release) => release.resolve_into(context, output),
         }
     }
 }
@@ -1,7 +1,7 @@
 use crate::context::parse::{self, ObjectKey};
 use crate::specs::{
     shell::{command, ShellCommand},
-    BuildUnit, Context, Resolve,
+    BuildUnit, Context, Health, Plan, Resolve,
 };
 use crate::yaml_example_doc;
 
@@ -9,7 +9,7 @@ use anyhow::{anyhow, Context as _, Result};
 use indexmap::IndexMap;
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::{fmt, process::Command};
 
 /// Installable binary package.
 #[doc = yaml_example_doc!("package.yaml")]
@@ -45,6 +45,7 @@ impl Package {
         self.iter_managers(context).any(|manager| manager.has(self)) || which_has(&self.name)
     }
 
+    #[allow(clippy::unnecessary_semicolon)]
     pub fn install(&self, context: &Context) -> Result<()> {
         if self.is_installed(context) {
             log::info!("Package already installed: {}", self.name);
@@ -69,6 +70,54 @@ impl Package {
         }
         Ok(())
     }
+
+    /// Report whether the package is installed
+    pub fn health(&self, context: &Context) -> Health {
+        match self.is_installed(context) {
+            true => Health::Satisfied("installed".to_string()),
+            false => Health::Unsatisfied("not installed".to_string()),
+        }
+    }
+
+    /// Describe the changes that [`Self::install`] would make
+    pub fn plan_install(&self, context: &Context) -> Plan {
+        if self.is_installed(context) {
+            return Plan::Satisfied;
+        }
+        self.iter_managers(context)
+            .find_map(|manager| manager.install_command(self).ok())
+            .map_or_else(
+                || Plan::Fail(format!("Package unavailable: {}", self.name)),
+                |command| Plan::Change(format!("run {command}")),
+            )
+    }
+
+    /// Describe the changes that [`Self::uninstall`] would make
+    pub fn plan_uninstall(&self, context: &Context) -> Plan {
+        match self
+            .iter_managers(context)
+            .filter(|manager| manager.has(self))
+            .map(|manager| manager.uninstall_command(self))
+            .collect::<Result<Vec<ShellCommand>>>()
+        {
+            Ok(commands) if commands.is_empty() => Plan::Satisfied,
+            Ok(commands) => Plan::Change(format!(
+                "run {}",
+                commands
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<String>>()
+                    .join(" and ")
+            )),
+            Err(error) => Plan::Fail(format!("{error:#}")),
+        }
+    }
+}
+
+impl fmt::Display for Package {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
 }
 
 impl Resolve for Package {
@@ -113,10 +162,15 @@ pub struct PackageManager {
 }
 
 impl PackageManager {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     /// Inject the alias of `package` into `command`.
     /// ```
     /// "apt install ${{ package.alias }}" -> "apt install my-package-alias"
     /// ```
+    #[allow(clippy::implicit_clone, clippy::non_std_lazy_statics)]
     fn inject_package(&self, command: &ShellCommand, package: &Package) -> Result<ShellCommand> {
         lazy_static! {
             static ref PACKAGE_KEY: parse::Key = Package::object_key("alias");
@@ -131,6 +185,29 @@ impl PackageManager {
         })
     }
 
+    /// Get `command` with the alias of `package` injected
+    fn package_command(
+        &self,
+        command: Option<&ShellCommand>,
+        command_name: &str,
+        package: &Package,
+    ) -> Result<ShellCommand> {
+        command
+            .with_context(|| format!("{}.{command_name} is not implemented", self.name))
+            .and_then(|command| self.inject_package(command, package))
+    }
+
+    /// Get the command that [`Self::install`] runs for `package`
+    fn install_command(&self, package: &Package) -> Result<ShellCommand> {
+        self.package_command(self.shell_install.as_ref(), "shell_install", package)
+    }
+
+    /// Get the command that [`Self::uninstall`] runs for `package`
+    fn uninstall_command(&self, package: &Package) -> Result<ShellCommand> {
+        self.package_command(self.shell_uninstall.as_ref(), "shell_uninstall", package)
+    }
+
+    #[allow(clippy::ref_option)]
     fn command<F, T>(
         &self,
         command: &Option<ShellCommand>,
@@ -193,6 +270,31 @@ impl PackageManager {
     pub fn is_available(&self) -> bool {
         which_has(&self.name)
     }
+
+    /// Report whether the package manager is available
+    pub fn health(&self) -> Health {
+        match self.is_available() {
+            true => Health::Satisfied("available".to_string()),
+            false => Health::Unsatisfied("not available".to_string()),
+        }
+    }
+
+    /// Describe the changes that [`Self::require`] would make
+    pub fn plan_require(&self) -> Plan {
+        if self.is_available() {
+            return Plan::Satisfied;
+        }
+        match self.shell_bootstrap {
+            Some(ref command) => Plan::Change(format!("run {command}")),
+            None => Plan::Fail(format!("{}.shell_bootstrap is not implemented", self.name)),
+        }
+    }
+}
+
+impl fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
 }
 
 impl Resolve for PackageManager {
@@ -340,6 +442,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn plan_install() {
+        #[rustfmt::skip]
+        let manager: PackageManager = serde_yaml::from_str("
+            name: fake_manager
+            shell_install: fake_manager install ${{ package.alias }}
+        ").unwrap();
+        let mut context = Context::default();
+        context.managers.insert(manager.name.clone(), manager);
+        let package: Package = serde_yaml::from_str("name: some_missing_package").unwrap();
+        let resolved = package.resolve(&mut context).unwrap();
+        let package = unpack!(resolved, BuildUnit::Package);
+        assert_eq!(
+            package.plan_install(&context),
+            Plan::Change("run `fake_manager install some_missing_package`".to_string())
+        );
+    }
+
+    #[test]
+    fn plan_install_unavailable() {
+        let context = Context::default();
+        let package: Package = serde_yaml::from_str("name: some_missing_package").unwrap();
+        assert!(matches!(package.plan_install(&context), Plan::Fail(_)));
+    }
+
+    #[test]
+    fn health() {
+        let context = Context::default();
+        let installed: Package = serde_yaml::from_str("name: cargo").unwrap();
+        let missing: Package = serde_yaml::from_str("name: some_missing_package").unwrap();
+        assert!(!installed.health(&context).is_unsatisfied());
+        assert!(missing.health(&context).is_unsatisfied());
+    }
+
+    #[test]
+    fn plan_install_satisfied() {
+        let context = Context::default();
+        let package: Package = serde_yaml::from_str("name: cargo").unwrap();
+        assert_eq!(package.plan_install(&context), Plan::Satisfied);
+    }
+
     #[test]
     fn which_has_cargo() {
         assert!(which_has("cargo"));
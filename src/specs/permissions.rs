@@ -0,0 +1,215 @@
+use crate::{
+    specs::{BuildUnit, Context, Health, Plan, Resolve},
+    yaml_example_doc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// Octal file permission bits (`"0600"`).
+///
+/// Permissions must be quoted strings. Values that YAML reads as integers (`600`, `0o600`)
+/// are rejected rather than guessing which base was intended.
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone, Copy)]
+#[serde(try_from = "PermissionsSpec", into = "String")]
+pub struct Permissions(u32);
+
+impl Permissions {
+    /// Default permissions of downloaded executables
+    pub const EXECUTABLE: Self = Self(0o755);
+
+    /// Current permission bits of the file at `path`
+    #[cfg(unix)]
+    fn current(path: &Path) -> Result<u32> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .map(|metadata| metadata.permissions().mode() & 0o7777)
+            .with_context(|| format!("Failed to read permissions: {}", path.display()))
+    }
+
+    /// Permissions are not supported on this platform
+    #[cfg(not(unix))]
+    fn current(path: &Path) -> Result<u32> {
+        bail!("File permissions are not supported on this platform: {path:?}")
+    }
+
+    /// Describe how the permissions of `path` differ from `self` (if they differ)
+    pub fn mismatch<P: AsRef<Path>>(self, path: P) -> Option<String> {
+        match Self::current(path.as_ref()) {
+            Ok(bits) if bits == self.0 => None,
+            Ok(bits) => Some(format!("mode {}, expected {self}", Self(bits))),
+            Err(e) => Some(format!("{e:#}")),
+        }
+    }
+
+    /// Set the permissions of `path` if they differ from `self`
+    pub fn apply<P: AsRef<Path>>(self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if Self::current(path)? == self.0 {
+            return Ok(());
+        }
+        log::info!("Setting mode {self} on {}", path.display());
+        Self::set(path, self.0)
+    }
+
+    #[cfg(unix)]
+    fn set(path: &Path, bits: u32) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(bits))
+            .with_context(|| format!("Failed to set permissions: {}", path.display()))
+    }
+
+    #[cfg(not(unix))]
+    fn set(path: &Path, _bits: u32) -> Result<()> {
+        bail!("File permissions are not supported on this platform: {path:?}")
+    }
+
+    /// Describe the changes that [`Self::apply`] would make
+    pub fn plan<P: AsRef<Path>>(self, path: P) -> Plan {
+        match Self::current(path.as_ref()) {
+            Ok(bits) if bits == self.0 => Plan::Satisfied,
+            Ok(_) => Plan::Change(format!("set mode {self}")),
+            Err(e) => Plan::Fail(format!("{e:#}")),
+        }
+    }
+}
+
+impl fmt::Display for Permissions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl From<Permissions> for String {
+    fn from(permissions: Permissions) -> Self {
+        permissions.to_string()
+    }
+}
+
+impl TryFrom<PermissionsSpec> for Permissions {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: PermissionsSpec) -> Result<Self> {
+        let digits = match spec {
+            PermissionsSpec::Integer(value) => {
+                bail!("File permissions must be a quoted octal string: {value}")
+            }
+            PermissionsSpec::String(value) => value,
+        };
+        let trimmed = digits.trim_start_matches("0o");
+        match u32::from_str_radix(trimmed, 8) {
+            Ok(bits) if bits <= 0o7777 => Ok(Self(bits)),
+            _ => bail!("Invalid octal file mode: {digits}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PermissionsSpec {
+    Integer(u32),
+    String(String),
+}
+
+/// Enforce the permissions of an existing file or directory
+#[doc = yaml_example_doc!("chmod.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct Chmod {
+    /// Path of the file or directory
+    path: PathBuf,
+    /// Required permissions
+    permissions: Permissions,
+}
+
+impl Chmod {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Report whether the permissions match
+    pub fn health(&self) -> Health {
+        match self.permissions.mismatch(&self.path) {
+            Some(mismatch) => Health::Unsatisfied(mismatch),
+            None => Health::Satisfied(format!("mode {}", self.permissions)),
+        }
+    }
+
+    /// Return true if the permissions match
+    pub fn is_applied(&self) -> bool {
+        self.permissions.mismatch(&self.path).is_none()
+    }
+
+    /// Set the permissions if they differ
+    pub fn apply(&self) -> Result<()> {
+        self.permissions.apply(&self.path)
+    }
+
+    /// Describe the changes that [`Self::apply`] would make
+    pub fn plan_apply(&self) -> Plan {
+        self.permissions.plan(&self.path)
+    }
+}
+
+impl fmt::Display for Chmod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.permissions, self.path.display())
+    }
+}
+
+impl Resolve for Chmod {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::Chmod(Self {
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_permissions() {
+        let parse = |yaml| serde_yaml::from_str::<Permissions>(yaml);
+        assert_eq!(parse("\"0600\"").unwrap(), Permissions(0o600));
+        assert!(parse("600").is_err());
+        assert!(parse("0o644").is_err());
+        assert_eq!(parse("\"0o644\"").unwrap(), Permissions(0o644));
+        assert!(parse("\"0800\"").is_err());
+        assert!(parse("\"17777\"").is_err());
+        assert_eq!(
+            serde_yaml::to_string(&Permissions(0o600)).unwrap(),
+            "'0600'\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn chmod_apply() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let chmod = Chmod {
+            path: dir.path().join("file"),
+            permissions: Permissions(0o600),
+        };
+        assert!(matches!(chmod.plan_apply(), Plan::Fail(_)));
+        fs::write(&chmod.path, "content").expect("Failed to write file");
+        Permissions::set(&chmod.path, 0o644).unwrap();
+        assert_eq!(
+            chmod.health(),
+            Health::Unsatisfied("mode 0644, expected 0600".to_string())
+        );
+        assert_eq!(
+            chmod.plan_apply(),
+            Plan::Change("set mode 0600".to_string())
+        );
+        chmod.apply().expect("Failed to set permissions");
+        assert!(chmod.is_applied());
+        assert_eq!(chmod.plan_apply(), Plan::Satisfied);
+    }
+}
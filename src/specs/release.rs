@@ -0,0 +1,427 @@
+use crate::{
+    digest,
+    specs::{
+        download::{self, Archive},
+        permissions::Permissions,
+        BuildUnit, Context, Health, Plan, Resolve,
+    },
+    state::Stamps,
+    yaml_example_doc,
+};
+
+use anyhow::{bail, Context as _, Result};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+fn default_api() -> String {
+    "https://api.github.com".to_string()
+}
+
+fn is_default_api(api: &str) -> bool {
+    api == default_api()
+}
+
+/// Release listing returned by the api
+#[derive(Debug, Deserialize)]
+struct ReleaseInfo {
+    tag_name: String,
+    assets: Vec<AssetInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssetInfo {
+    name: String,
+    browser_download_url: String,
+    /// Digest of the asset (`sha256:<hex>`)
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// File matches the installed release
+    Current,
+    /// File does not exist
+    Missing,
+    /// File differs from the installed release
+    Changed,
+}
+
+/// Binary installed from a GitHub release asset
+///
+/// The [`asset`][Self::asset] glob pattern selects the release asset and may contain
+/// `${{ locale.platform }}` and `${{ locale.arch }}`. Archives are extracted as with
+/// [`!download`][crate::specs::download::Download]. The asset is verified against
+/// [`sha256`][Self::sha256] or the digest published with the release. Assets without
+/// either are only installed when [`insecure`][Self::insecure] is set.
+#[doc = yaml_example_doc!("github_release.yaml")]
+#[derive(Debug, PartialEq, Deserialize, Serialize, Clone)]
+pub struct GithubRelease {
+    /// Repository of the release (`owner/name`)
+    repo: String,
+    /// Release tag (latest release by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    /// Release asset name (glob pattern)
+    asset: String,
+    /// Installed file path
+    path: PathBuf,
+    /// Archive member to install (glob pattern)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extract: Option<String>,
+    /// Hex encoded SHA-256 checksum of the asset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Install an asset that has no checksum
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    insecure: bool,
+    /// Permissions of the installed file (`0755` by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    /// Base url of the release api
+    #[serde(default = "default_api", skip_serializing_if = "is_default_api")]
+    api: String,
+    /// Location of the release stamps
+    #[serde(skip)]
+    stamps: Stamps,
+}
+
+impl GithubRelease {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn permissions(&self) -> Option<Permissions> {
+        self.permissions
+            .or(cfg!(unix).then_some(Permissions::EXECUTABLE))
+    }
+
+    /// Url of the release listing
+    fn release_url(&self) -> String {
+        let api = self.api.trim_end_matches('/');
+        match self.version {
+            Some(ref version) => format!("{api}/repos/{}/releases/tags/{version}", self.repo),
+            None => format!("{api}/repos/{}/releases/latest", self.repo),
+        }
+    }
+
+    /// Fetch the release listing
+    fn release(&self) -> Result<ReleaseInfo> {
+        let url = self.release_url();
+        let response = minreq::get(&url)
+            .with_header("Accept", "application/vnd.github+json")
+            .with_header("User-Agent", "yurt")
+            .send()
+            .with_context(|| format!("Failed to reach release api: {url}"))?;
+        if !(200..300).contains(&response.status_code) {
+            bail!(
+                "Release request failed: {} {} ({url})",
+                response.status_code,
+                response.reason_phrase
+            );
+        }
+        serde_json::from_slice(response.as_bytes())
+            .with_context(|| format!("Failed to parse release: {url}"))
+    }
+
+    /// Keep the stamps of installed releases in `stamps`
+    pub fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// Path of the stamp of the installed release
+    fn stamp(&self) -> Result<PathBuf> {
+        let key = format!(
+            "{} {} {} {}",
+            self.repo,
+            self.asset,
+            self.extract.as_deref().unwrap_or_default(),
+            self.path.display()
+        );
+        self.stamps.path("release", &key)
+    }
+
+    /// Stamp recorded for a file with digest `hash` installed from `tag`
+    fn stamp_content(tag: &str, hash: &str) -> String {
+        format!("{tag} {hash}\n")
+    }
+
+    /// Compare the installed file against the stamp
+    fn status(&self) -> Status {
+        let Ok(hash) = digest::sha256_file(&self.path) else {
+            return Status::Missing;
+        };
+        let stamp = self
+            .stamp()
+            .and_then(|stamp| Ok(fs::read_to_string(stamp)?))
+            .ok();
+        let current = stamp.is_some_and(|stamp| match stamp.split_once(' ') {
+            Some((tag, _)) => {
+                self.version.as_ref().is_none_or(|version| version == tag)
+                    && stamp == Self::stamp_content(tag, &hash)
+            }
+            None => false,
+        });
+        match current {
+            true => Status::Current,
+            false => Status::Changed,
+        }
+    }
+
+    /// Report whether the release is installed
+    pub fn health(&self) -> Health {
+        match self.status() {
+            Status::Current => match self
+                .permissions()
+                .and_then(|mode| mode.mismatch(&self.path))
+            {
+                Some(mismatch) => Health::Unsatisfied(mismatch),
+                None => Health::Satisfied("installed".to_string()),
+            },
+            Status::Missing => Health::Unsatisfied("missing".to_string()),
+            Status::Changed => Health::Unsatisfied("release differs".to_string()),
+        }
+    }
+
+    /// Return true if the release is installed
+    pub fn is_installed(&self) -> bool {
+        self.status() == Status::Current
+    }
+
+    /// Find, download, verify, and install the release asset if it is not current
+    pub fn install(&self) -> Result<()> {
+        if self.status() != Status::Current {
+            log::info!("Installing {self}");
+            let release = self.release()?;
+            let pattern = Pattern::new(&self.asset)
+                .with_context(|| format!("Invalid asset pattern: {}", self.asset))?;
+            let asset = release
+                .assets
+                .iter()
+                .find(|asset| pattern.matches(&asset.name))
+                .with_context(|| {
+                    format!(
+                        "No asset of {} {} matches: {}",
+                        self.repo, release.tag_name, self.asset
+                    )
+                })?;
+            let expected = self.sha256.as_deref().or_else(|| {
+                asset
+                    .digest
+                    .as_deref()
+                    .and_then(|digest| digest.strip_prefix("sha256:"))
+            });
+            if expected.is_none() && !self.insecure {
+                bail!(
+                    "No checksum available for {} (set sha256 or insecure)",
+                    asset.name
+                );
+            }
+            let bytes = download::fetch(&asset.browser_download_url)?;
+            match expected {
+                Some(expected) => download::verify(&bytes, expected)
+                    .with_context(|| format!("Failed to verify {}", asset.name))?,
+                None => log::warn!("Installing {} without a checksum", asset.name),
+            }
+            let bytes = match self.extract {
+                Some(ref member) => Archive::from_url(&asset.name)?.extract(&bytes, member)?,
+                None => bytes,
+            };
+            download::install_file(&self.path, &bytes)?;
+            let stamp = self.stamp()?;
+            if let Some(dir) = stamp.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(
+                stamp,
+                Self::stamp_content(&release.tag_name, &digest::sha256(&bytes)),
+            )
+            .context("Failed to write release stamp")?;
+        }
+        match self.permissions() {
+            Some(mode) => mode.apply(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::install`] would make
+    pub fn plan_install(&self) -> Plan {
+        match self.status() {
+            Status::Current => match self.permissions() {
+                Some(mode) => mode.plan(&self.path),
+                None => Plan::Satisfied,
+            },
+            Status::Missing => Plan::Change("install release".to_string()),
+            Status::Changed => Plan::Change("replace changed file".to_string()),
+        }
+    }
+
+    /// Remove the file if it is still current
+    pub fn remove(&self) -> Result<()> {
+        match self.status() {
+            Status::Current => {
+                log::info!("Removing {self}");
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("Failed to remove file: {self}"))?;
+                fs::remove_file(self.stamp()?).context("Failed to remove release stamp")
+            }
+            Status::Changed => {
+                log::warn!("Keeping modified file {self}");
+                Ok(())
+            }
+            Status::Missing => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match self.status() {
+            Status::Current => Plan::Change("remove file".to_string()),
+            Status::Changed => Plan::Change("keep modified file".to_string()),
+            Status::Missing => Plan::Satisfied,
+        }
+    }
+}
+
+impl fmt::Display for GithubRelease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(ref version) => {
+                write!(f, "{}@{version} -> {}", self.repo, self.path.display())
+            }
+            None => write!(f, "{} -> {}", self.repo, self.path.display()),
+        }
+    }
+}
+
+impl Resolve for GithubRelease {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::GithubRelease(Self {
+            repo: context.parse_str(&self.repo)?,
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            version: self
+                .version
+                .map(|version| context.parse_str(&version))
+                .transpose()?,
+            asset: context.parse_str(&self.asset)?,
+            extract: self
+                .extract
+                .map(|member| context.parse_str(&member))
+                .transpose()?,
+            api: context.parse_str(&self.api)?,
+            stamps: context.stamps.clone(),
+            ..self
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::tests::{ok_response, serve};
+
+    const SCRIPT: &str = "#!/bin/sh\necho yurt\n";
+
+    fn fixture(api: &str, version: Option<&str>) -> (tempfile::TempDir, GithubRelease) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let release = GithubRelease {
+            repo: "owner/tool".to_string(),
+            version: version.map(String::from),
+            asset: "tool-*-linux".to_string(),
+            path: dir.path().join("bin").join("tool"),
+            extract: None,
+            sha256: None,
+            insecure: false,
+            permissions: None,
+            api: api.to_string(),
+            stamps: Stamps::new(dir.path().join("stamps")),
+        };
+        (dir, release)
+    }
+
+    fn listing(download: &str, digest: Option<&str>) -> String {
+        serde_json::json!({
+            "tag_name": "v1.0",
+            "assets": [
+                { "name": "tool-v1.0-darwin", "browser_download_url": "http://invalid/darwin" },
+                {
+                    "name": "tool-v1.0-linux",
+                    "browser_download_url": format!("{download}/tool-v1.0-linux"),
+                    "digest": digest,
+                },
+            ],
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn install_tagged_release() {
+        let (download, download_server) = serve(vec![ok_response("", SCRIPT)]);
+        let digest = format!("sha256:{}", digest::sha256(SCRIPT.as_bytes()));
+        let (api, api_server) = serve(vec![ok_response("", &listing(&download, Some(&digest)))]);
+        let (_dir, release) = fixture(&api, Some("v1.0"));
+        assert_eq!(
+            release.plan_install(),
+            Plan::Change("install release".to_string())
+        );
+        release.install().expect("Failed to install release");
+        let requests = api_server.join().unwrap();
+        assert!(requests[0].starts_with("GET /repos/owner/tool/releases/tags/v1.0 "));
+        assert!(download_server.join().unwrap()[0].starts_with("GET /tool-v1.0-linux "));
+        assert_eq!(fs::read_to_string(&release.path).unwrap(), SCRIPT);
+        assert!(release.is_installed());
+        // Rerun is a no-op (the servers only answer once)
+        release.install().expect("Failed to rerun install");
+        // A different version is not current
+        let pinned = GithubRelease {
+            version: Some("v2.0".to_string()),
+            ..release.clone()
+        };
+        assert!(!pinned.is_installed());
+        // Stamps are found for units reloaded from the state file
+        let spec = serde_yaml::to_string(&release).unwrap();
+        let mut reloaded: GithubRelease = serde_yaml::from_str(&spec).unwrap();
+        reloaded.set_stamps(release.stamps.clone());
+        assert!(reloaded.is_installed());
+        let stamp = release.stamp().unwrap();
+        reloaded.remove().expect("Failed to remove release");
+        assert!(!release.path.exists());
+        assert!(!stamp.exists());
+    }
+
+    #[test]
+    fn install_digest_mismatch() {
+        let (download, download_server) = serve(vec![ok_response("", SCRIPT)]);
+        let digest = format!("sha256:{}", digest::sha256(b"other"));
+        let (api, api_server) = serve(vec![ok_response("", &listing(&download, Some(&digest)))]);
+        let (_dir, release) = fixture(&api, None);
+        assert!(release.install().is_err());
+        assert!(api_server.join().unwrap()[0].starts_with("GET /repos/owner/tool/releases/latest "));
+        download_server.join().unwrap();
+        assert!(!release.path.exists());
+    }
+
+    #[test]
+    fn install_without_digest() {
+        let (download, download_server) = serve(vec![ok_response("", SCRIPT)]);
+        let body = listing(&download, None);
+        let (api, api_server) = serve(vec![ok_response("", &body), ok_response("", &body)]);
+        let (_dir, release) = fixture(&api, None);
+        assert!(release.install().is_err());
+        assert!(!release.path.exists());
+        let insecure = GithubRelease {
+            insecure: true,
+            ..release
+        };
+        insecure.install().expect("Failed to install release");
+        api_server.join().unwrap();
+        // Only the insecure install downloads the asset
+        assert_eq!(download_server.join().unwrap().len(), 1);
+        assert_eq!(fs::read_to_string(&insecure.path).unwrap(), SCRIPT);
+    }
+}
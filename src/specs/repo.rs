@@ -1,12 +1,13 @@
 use crate::{
     context::{parse::ObjectKey, Context},
-    specs::{BuildUnit, Resolve},
+    specs::{BuildUnit, Health, Plan, Resolve},
     yaml_example_doc,
 };
 
 use anyhow::{Context as _, Result};
-use git2::Repository;
+use git2::{Branch, Repository, StatusOptions};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// `git` repository
 #[doc = yaml_example_doc!("repo.yaml")]
@@ -37,6 +38,48 @@ impl Repo {
         self.open().is_ok()
     }
 
+    /// Report whether the repository is available, and its working tree state
+    pub fn health(&self) -> Health {
+        match self.open() {
+            Ok(repo) => Health::Satisfied(match Self::describe(&repo) {
+                Ok(description) => description,
+                Err(error) => format!("{error:#}"),
+            }),
+            Err(_) => Health::Unsatisfied("not cloned".to_string()),
+        }
+    }
+
+    /// Summarize uncommitted changes and divergence from the upstream branch
+    fn describe(repo: &Repository) -> Result<String> {
+        let dirty = !repo
+            .statuses(Some(StatusOptions::new().include_untracked(true)))
+            .context("Failed to get repository status")?
+            .is_empty();
+        let tree = match dirty {
+            true => "dirty",
+            false => "clean",
+        };
+        let upstream = repo.head().ok().and_then(|head| {
+            let local = head.target()?;
+            let upstream = Branch::wrap(head).upstream().ok()?.get().target()?;
+            repo.graph_ahead_behind(local, upstream).ok()
+        });
+        Ok(match upstream {
+            Some((0, 0)) => format!("{tree}, up to date"),
+            Some((ahead, behind)) => format!("{tree}, {ahead} ahead, {behind} behind"),
+            None => format!("{tree}, no upstream"),
+        })
+    }
+
+    /// Describe the changes that [`Self::require`] would make
+    pub fn plan_require(&self) -> Plan {
+        match self.is_available() {
+            true => Plan::Satisfied,
+            false => Plan::Change(format!("clone {} into {:?}", self.url, self.path)),
+        }
+    }
+
+    #[allow(clippy::double_ended_iterator_last)]
     fn name(&self) -> Result<&str> {
         self.path
             .split(&['/', '\\'])
@@ -46,11 +89,18 @@ impl Repo {
     }
 }
 
+impl fmt::Display for Repo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", &self.path)
+    }
+}
+
 impl ObjectKey for Repo {
     const OBJECT_NAME: &'static str = "repo";
 }
 
 impl Resolve for Repo {
+    #[allow(clippy::implicit_clone)]
     fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
         let new = Self {
             path: context.parse_path(&self.path)?,
@@ -71,7 +121,8 @@ impl Resolve for Repo {
 
 #[cfg(test)]
 mod tests {
-    use super::Repo;
+    use super::{Health, Repo};
+    use git2::Repository;
 
     fn repo(path: &str) -> Repo {
         Repo {
@@ -94,4 +145,30 @@ mod tests {
     fn windows_path() {
         assert_eq!(repo("path\\to\\my-repo").name().unwrap(), "my-repo");
     }
+
+    #[test]
+    fn health_missing() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let missing = repo(dir.path().join("missing").to_str().unwrap());
+        assert_eq!(
+            missing.health(),
+            Health::Unsatisfied("not cloned".to_string())
+        );
+    }
+
+    #[test]
+    fn health_dirty() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        Repository::init(dir.path()).expect("Failed to init repository");
+        let local = repo(dir.path().to_str().unwrap());
+        assert_eq!(
+            local.health(),
+            Health::Satisfied("clean, no upstream".to_string())
+        );
+        std::fs::write(dir.path().join("file"), "content").expect("Failed to write file");
+        assert_eq!(
+            local.health(),
+            Health::Satisfied("dirty, no upstream".to_string())
+        );
+    }
 }
@@ -1,11 +1,11 @@
 use crate::{
-    specs::{BuildUnit, Context, Resolve},
+    specs::{BuildUnit, Context, Plan, Resolve},
     yaml_example_doc,
 };
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::{env, ffi::OsStr, path::Path, process::Command};
+use std::{env, ffi::OsStr, fmt, path::Path, process::Command};
 
 pub mod command {
     use anyhow::{Context as _, Result};
@@ -39,6 +39,13 @@ pub mod command {
     pub fn call(command: &mut Command) -> Result<()> {
         call_unchecked(command).and_then(|out| check_output(&out, command))
     }
+
+    /// Call `command` and return its trimmed stdout
+    pub fn call_output(command: &mut Command) -> Result<String> {
+        let out = call_unchecked(command)?;
+        check_output(&out, &command)?;
+        Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -73,6 +80,7 @@ pub struct Shell {
     command: String,
 }
 
+#[allow(clippy::used_underscore_items)]
 impl Shell {
     pub fn from_env() -> Self {
         match env::var("SHELL") {
@@ -99,6 +107,10 @@ impl Shell {
     pub fn exec_bool(&self, command: &str) -> Result<bool> {
         command::call_bool(&mut self._exec(command))
     }
+
+    pub fn exec_output(&self, command: &str) -> Result<String> {
+        command::call_output(&mut self._exec(command))
+    }
 }
 
 impl Default for Shell {
@@ -157,6 +169,16 @@ impl ShellCommand {
     pub fn exec_bool(&self) -> Result<bool> {
         self.shell.exec_bool(&self.command)
     }
+
+    pub fn exec_output(&self) -> Result<String> {
+        self.shell.exec_output(&self.command)
+    }
+}
+
+impl fmt::Display for ShellCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`", self.command.trim())
+    }
 }
 
 impl From<String> for ShellCommand {
@@ -227,9 +249,24 @@ impl ShellHook {
     }
 
     #[inline]
+    #[allow(clippy::obfuscated_if_else)]
     pub fn exec_for(&self, hook: &Hook) -> Result<()> {
         self.applies(hook).then(|| self.exec()).unwrap_or(Ok(()))
     }
+
+    /// Describe the changes that [`Self::exec_for`] would make
+    pub fn plan_for(&self, hook: &Hook) -> Plan {
+        match self.applies(hook) {
+            true => Plan::Change(format!("run {}", self.exec)),
+            false => Plan::Skip,
+        }
+    }
+}
+
+impl fmt::Display for ShellHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.exec.fmt(f)
+    }
 }
 
 impl Resolve for ShellHook {
@@ -328,6 +365,20 @@ mod tests {
             assert_eq!(cmd.command, "echo 'hello world!'");
         }
 
+        #[test]
+        fn hook_plan() {
+            #[rustfmt::skip]
+            let hook: ShellHook = serde_yaml::from_str("
+                on: [ install ]
+                exec: echo 'hello world!'
+            ").unwrap();
+            assert_eq!(
+                hook.plan_for(&Hook::Install),
+                Plan::Change("run `echo 'hello world!'`".to_string())
+            );
+            assert_eq!(hook.plan_for(&Hook::Uninstall), Plan::Skip);
+        }
+
         #[test]
         fn command_success() {
             ShellCommand::from("echo 'hello world!'".to_string())
@@ -0,0 +1,315 @@
+use crate::{
+    context::Variables,
+    digest,
+    specs::{permissions::Permissions, BuildUnit, Context, Health, Plan, Resolve},
+    state::Stamps,
+    yaml_example_doc,
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    /// Rendered file matches the template
+    Current,
+    /// Rendered file does not exist
+    Missing,
+    /// Rendered file differs from the template
+    Changed,
+}
+
+/// File generated from a template ([`source`][Self::source] -> [`path`][Self::path])
+///
+/// Keys (`${{ }}`) in the template content are replaced with the values that were
+/// set when the unit was resolved. The file is only rewritten when the hash of
+/// the rendered content changes, and is only removed if it was not edited since it
+/// was written.
+#[doc = yaml_example_doc!("template.yaml")]
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Template {
+    /// Path of the template file
+    source: PathBuf,
+    /// Path of the rendered file
+    path: PathBuf,
+    /// Permissions of the rendered file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<Permissions>,
+    /// Variables used to render the template (captured during resolution)
+    #[serde(skip)]
+    variables: Option<Variables>,
+    /// Location of the hash recorded when the file is written
+    #[serde(skip)]
+    stamps: Stamps,
+}
+
+impl Template {
+    /// Path managed by the unit
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Render the template content
+    fn render(&self) -> Result<String> {
+        let variables = self
+            .variables
+            .as_ref()
+            .context("Template has not been resolved")?;
+        fs::read_to_string(&self.source)
+            .with_context(|| format!("Failed to read template: {}", self.source.display()))
+            .and_then(|content| variables.parse_str(&content))
+            .with_context(|| format!("Failed to render template: {self}"))
+    }
+
+    /// Keep the hash of the written file in `stamps`
+    pub fn set_stamps(&mut self, stamps: Stamps) {
+        self.stamps = stamps;
+    }
+
+    /// Path of the stamp that records the hash of the written file
+    fn stamp(&self) -> Result<PathBuf> {
+        self.stamps.path("template", &self.path.to_string_lossy())
+    }
+
+    /// Compare the rendered file against the hash recorded when it was written
+    fn written_status(&self) -> Status {
+        let Ok(hash) = digest::sha256_file(&self.path) else {
+            return Status::Missing;
+        };
+        match self
+            .stamp()
+            .and_then(|stamp| Ok(fs::read_to_string(stamp)?))
+        {
+            Ok(written) if written == hash => Status::Current,
+            _ => Status::Changed,
+        }
+    }
+
+    /// Compare the rendered file against `rendered` content
+    fn status(&self, rendered: &str) -> Status {
+        match digest::sha256_file(&self.path) {
+            Ok(hash) if hash == digest::sha256(rendered.as_bytes()) => Status::Current,
+            Ok(_) => Status::Changed,
+            Err(_) => Status::Missing,
+        }
+    }
+
+    /// Report whether the rendered file is up to date
+    pub fn health(&self) -> Health {
+        match self.render() {
+            Ok(rendered) => match self.status(&rendered) {
+                Status::Current => {
+                    match self.permissions.and_then(|mode| mode.mismatch(&self.path)) {
+                        Some(mismatch) => Health::Unsatisfied(mismatch),
+                        None => Health::Satisfied(format!(
+                            "sha256:{}",
+                            &digest::sha256(rendered.as_bytes())[..12]
+                        )),
+                    }
+                }
+                Status::Missing => Health::Unsatisfied("missing".to_string()),
+                Status::Changed => Health::Unsatisfied("content differs".to_string()),
+            },
+            Err(error) => Health::Unsatisfied(format!("{error:#}")),
+        }
+    }
+
+    /// Return true if the rendered file is up to date
+    pub fn is_current(&self) -> bool {
+        self.render()
+            .is_ok_and(|rendered| self.status(&rendered) == Status::Current)
+    }
+
+    /// Write the rendered file if its content changed
+    pub fn write(&self) -> Result<()> {
+        let rendered = self.render()?;
+        if self.status(&rendered) != Status::Current {
+            log::info!("Rendering {self}");
+            if let Some(dir) = self.path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&self.path, &rendered)
+                .with_context(|| format!("Failed to write rendered template: {self}"))?;
+            let stamp = self.stamp()?;
+            if let Some(dir) = stamp.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(stamp, digest::sha256(rendered.as_bytes()))
+                .context("Failed to write template stamp")?;
+        }
+        match self.permissions {
+            Some(mode) => mode.apply(&self.path),
+            None => Ok(()),
+        }
+    }
+
+    /// Remove the rendered file if it was not edited since it was written
+    pub fn remove(&self) -> Result<()> {
+        match self.written_status() {
+            Status::Current => {
+                log::info!("Removing {}", self.path.display());
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("Failed to remove rendered template: {self}"))?;
+                fs::remove_file(self.stamp()?).context("Failed to remove template stamp")
+            }
+            Status::Changed => {
+                log::warn!("Keeping modified file {}", self.path.display());
+                Ok(())
+            }
+            Status::Missing => Ok(()),
+        }
+    }
+
+    /// Describe the changes that [`Self::write`] would make
+    pub fn plan_write(&self) -> Plan {
+        match self.render() {
+            Ok(rendered) => match self.status(&rendered) {
+                Status::Current => match self.permissions {
+                    Some(mode) => mode.plan(&self.path),
+                    None => Plan::Satisfied,
+                },
+                Status::Missing => Plan::Change("render template".to_string()),
+                Status::Changed => Plan::Change("rewrite changed content".to_string()),
+            },
+            Err(error) => Plan::Fail(format!("{error:#}")),
+        }
+    }
+
+    /// Describe the changes that [`Self::remove`] would make
+    pub fn plan_remove(&self) -> Plan {
+        match self.written_status() {
+            Status::Current => Plan::Change("remove rendered file".to_string()),
+            Status::Changed => Plan::Change("keep modified file".to_string()),
+            Status::Missing => Plan::Satisfied,
+        }
+    }
+}
+
+/// Templates are identified by their paths
+impl PartialEq for Template {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.path == other.path
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.source.display(), self.path.display())
+    }
+}
+
+impl Resolve for Template {
+    fn resolve(self, context: &mut Context) -> Result<BuildUnit> {
+        Ok(BuildUnit::Template(Self {
+            source: context
+                .parse_path(self.source.to_str().unwrap_or(""))?
+                .into(),
+            path: context.parse_path(self.path.to_str().unwrap_or(""))?.into(),
+            permissions: self.permissions,
+            variables: Some(context.capture()),
+            stamps: context.stamps.clone(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::specs::ShellCommand;
+
+    fn fixture(text: &str) -> (tempfile::TempDir, Template) {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let template_path = dir.path().join("file.template");
+        fs::write(&template_path, text).expect("Failed to write template");
+        let mut context = Context::default();
+        context.stamps = Stamps::new(dir.path().join("stamps"));
+        context.variables.try_push("vars.name", "yurt").unwrap();
+        let template = Template {
+            source: template_path,
+            path: dir.path().join("nested").join("file"),
+            permissions: None,
+            variables: None,
+            stamps: Stamps::default(),
+        };
+        match template.resolve(&mut context).unwrap() {
+            BuildUnit::Template(template) => (dir, template),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn write_and_remove() {
+        let (_dir, template) = fixture("name = ${{ vars.name }}");
+        assert_eq!(
+            template.plan_write(),
+            Plan::Change("render template".into())
+        );
+        assert!(template.health().is_unsatisfied());
+        template.write().expect("Failed to write template");
+        assert_eq!(fs::read_to_string(&template.path).unwrap(), "name = yurt");
+        assert_eq!(template.plan_write(), Plan::Satisfied);
+        assert!(template.is_current());
+        template.remove().expect("Failed to remove template");
+        assert!(!template.path.exists());
+    }
+
+    #[test]
+    fn rewrite_changed() {
+        let (_dir, template) = fixture("name = ${{ vars.name }}");
+        template.write().expect("Failed to write template");
+        fs::write(&template.path, "edited").unwrap();
+        assert_eq!(
+            template.plan_write(),
+            Plan::Change("rewrite changed content".into())
+        );
+        template.write().expect("Failed to write template");
+        assert_eq!(fs::read_to_string(&template.path).unwrap(), "name = yurt");
+    }
+
+    #[test]
+    fn keep_edited() {
+        let (_dir, template) = fixture("name = ${{ vars.name }}");
+        template.write().expect("Failed to write template");
+        fs::write(&template.path, "edited").unwrap();
+        assert_eq!(
+            template.plan_remove(),
+            Plan::Change("keep modified file".into())
+        );
+        template.remove().expect("Failed to remove template");
+        assert_eq!(fs::read_to_string(&template.path).unwrap(), "edited");
+    }
+
+    #[test]
+    fn render_facts() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let source = dir.path().join("file.template");
+        fs::write(&source, "${{ fact.greeting }}").unwrap();
+        let mut context = Context::default();
+        context.add_fact(
+            "greeting".to_string(),
+            ShellCommand::from("echo hello".to_string()),
+        );
+        let template = Template {
+            source,
+            path: dir.path().join("file"),
+            permissions: None,
+            variables: None,
+            stamps: Stamps::default(),
+        };
+        let Ok(BuildUnit::Template(template)) = template.resolve(&mut context) else {
+            unreachable!();
+        };
+        assert_eq!(template.render().unwrap(), "hello");
+    }
+
+    #[test]
+    fn render_missing_key() {
+        let (_dir, template) = fixture("${{ vars.missing }}");
+        assert!(template.write().is_err());
+        assert!(matches!(template.plan_write(), Plan::Fail(_)));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::{
+    config::ResolvedConfig,
+    context::Context,
+    digest,
+    specs::{BuildSpec, BuildUnit},
+};
+
+use anyhow::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Build unit that was applied by `yurt install`
+#[derive(Debug, Deserialize, Serialize)]
+struct StateEntry {
+    /// Resolved build unit
+    unit: BuildSpec,
+    /// Seconds since the unix epoch when the unit was last applied
+    timestamp: u64,
+}
+
+/// Record of every build unit that has been applied.
+///
+/// Used to revert units that were dropped from the build file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+    #[serde(default)]
+    units: Vec<StateEntry>,
+}
+
+impl State {
+    /// Default state file path (`$YURT_STATE_FILE` or `<state dir>/yurt/state.yaml`)
+    pub fn default_path() -> Result<PathBuf> {
+        if let Ok(path) = env::var("YURT_STATE_FILE") {
+            return Ok(PathBuf::from(path));
+        }
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map(|dir| dir.join("yurt").join("state.yaml"))
+            .context("Failed to locate state directory")
+    }
+
+    /// Load the state file at `path` (empty if the file does not exist)
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => serde_yaml::from_reader(BufReader::new(file))
+                .context("Failed to deserialize state file"),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).context("Failed to open state file"),
+        }
+    }
+
+    /// Write the state file to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create state directory")?;
+        }
+        let yaml = serde_yaml::to_string(self).context("Failed to serialize state")?;
+        fs::write(path, yaml).context("Failed to write state file")
+    }
+
+    /// Iterate over the recorded units
+    pub fn units(&self) -> impl Iterator<Item = BuildUnit> + '_ {
+        self.units
+            .iter()
+            .filter_map(|entry| BuildUnit::try_from(entry.unit.clone()).ok())
+    }
+
+    fn entry(unit: &BuildUnit) -> StateEntry {
+        StateEntry {
+            unit: unit.clone().into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+        }
+    }
+
+    /// Record `unit` as applied (replaces existing records of the same unit)
+    pub fn record(&mut self, unit: &BuildUnit) {
+        let entry = Self::entry(unit);
+        match self.position(unit) {
+            Some(index) => self.units[index] = entry,
+            None => self.units.push(entry),
+        }
+    }
+
+    /// Replace an existing record of the same unit with `unit`
+    pub fn update(&mut self, unit: &BuildUnit) {
+        if let Some(index) = self.position(unit) {
+            self.units[index] = Self::entry(unit);
+        }
+    }
+
+    /// Remove the record of `unit`
+    pub fn forget(&mut self, unit: &BuildUnit) {
+        if let Some(index) = self.position(unit) {
+            self.units.remove(index);
+        }
+    }
+
+    /// Recorded units that are missing from `build`
+    pub fn stale(&self, build: &ResolvedConfig) -> Vec<BuildUnit> {
+        self.units().filter(|unit| !build.contains(unit)).collect()
+    }
+
+    /// Extend `context` with recorded package managers that it does not define.
+    ///
+    /// Allows stale packages to be removed after their manager was dropped.
+    pub fn extend_context(&self, context: &mut Context) {
+        for unit in self.units() {
+            if let BuildUnit::PackageManager(manager) = unit {
+                if !context.managers.contains_key(manager.name()) {
+                    context.managers.insert(manager.name().to_string(), manager);
+                }
+            }
+        }
+    }
+
+    fn position(&self, unit: &BuildUnit) -> Option<usize> {
+        self.units.iter().position(|entry| {
+            BuildUnit::try_from(entry.unit.clone()).is_ok_and(|recorded| recorded.is_same(unit))
+        })
+    }
+}
+
+/// Records written by units to detect changes made outside of yurt.
+///
+/// Stamps are kept in a `stamps` directory next to the state file.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stamps {
+    /// Stamp directory (next to the default state file if unset)
+    dir: Option<PathBuf>,
+}
+
+impl Stamps {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self {
+            dir: Some(dir.into()),
+        }
+    }
+
+    /// Stamps kept next to the state file at `path`
+    pub fn for_state_file(path: &Path) -> Self {
+        Self::new(path.with_file_name("stamps"))
+    }
+
+    /// Path of the stamp recorded by a `kind` unit for `key`
+    pub fn path(&self, kind: &str, key: &str) -> Result<PathBuf> {
+        let dir = match self.dir {
+            Some(ref dir) => dir.clone(),
+            None => State::default_path()?.with_file_name("stamps"),
+        };
+        Ok(dir.join(kind).join(digest::sha256(key.as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use pretty_assertions::assert_eq;
+
+    fn resolve(yaml: &str) -> ResolvedConfig {
+        serde_yaml::from_str::<Config>(yaml)
+            .expect("Deserialization failed")
+            .resolve(Context::default())
+            .expect("Resolution failed")
+    }
+
+    fn units(build: &ResolvedConfig) -> Vec<BuildUnit> {
+        let mut units = Vec::new();
+        build
+            .for_each_unit(|unit, _| {
+                units.push(unit.clone());
+                Ok(())
+            })
+            .unwrap();
+        units
+    }
+
+    fn record_all(state: &mut State, build: &ResolvedConfig) {
+        build
+            .for_each_unit(|unit, _| {
+                state.record(unit);
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn record_unique() {
+        let build = resolve("build: [ !package { name: a }, !package { name: b } ]");
+        let mut state = State::default();
+        record_all(&mut state, &build);
+        record_all(&mut state, &build);
+        assert_eq!(state.units().count(), 2);
+    }
+
+    #[test]
+    fn stale_units() {
+        let old = resolve("build: [ !package { name: a }, !link { source: s, target: t } ]");
+        let new = resolve("build: [ !package { name: a } ]");
+        let mut state = State::default();
+        record_all(&mut state, &old);
+        let dropped = state.stale(&new);
+        assert_eq!(dropped.len(), 1);
+        assert!(matches!(dropped[0], BuildUnit::Link(_)));
+        state.forget(&dropped[0]);
+        assert!(state.stale(&new).is_empty());
+    }
+
+    #[test]
+    fn changed_attributes() {
+        let old = resolve("build: [ !link { source: s, target: t } ]");
+        let new = resolve("build: [ !link { source: s, target: u, mode: copy } ]");
+        let mut state = State::default();
+        record_all(&mut state, &old);
+        assert!(state.stale(&new).is_empty());
+        record_all(&mut state, &new);
+        assert_eq!(state.units().collect::<Vec<_>>(), units(&new));
+    }
+
+    #[test]
+    fn update_existing() {
+        let old = resolve("build: [ !file { path: f, content: a } ]");
+        let new = resolve("build: [ !file { path: f, content: b }, !package { name: a } ]");
+        let mut state = State::default();
+        record_all(&mut state, &old);
+        for unit in units(&new) {
+            state.update(&unit);
+        }
+        assert_eq!(state.units().collect::<Vec<_>>(), units(&new)[..1]);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let path = dir.path().join("nested").join("state.yaml");
+        let build = resolve("build: [ !package { name: a }, !link { source: s, target: t } ]");
+        let mut state = State::default();
+        record_all(&mut state, &build);
+        state.save(&path).expect("Failed to save state");
+        let loaded = State::from_path(&path).expect("Failed to load state");
+        assert_eq!(
+            loaded.units().collect::<Vec<_>>(),
+            state.units().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stamps_next_to_state_file() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let stamps = Stamps::for_state_file(&dir.path().join("state.yaml"));
+        let stamp = stamps.path("template", "/home/user/.gitconfig").unwrap();
+        assert!(stamp.starts_with(dir.path().join("stamps").join("template")));
+        assert_ne!(
+            stamp,
+            stamps.path("template", "/home/user/.bashrc").unwrap()
+        );
+    }
+
+    #[test]
+    fn load_missing() {
+        let dir = tempfile::tempdir().expect("Failed to create tempdir");
+        let state = State::from_path(dir.path().join("state.yaml")).unwrap();
+        assert_eq!(state.units().count(), 0);
+    }
+}